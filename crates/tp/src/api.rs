@@ -0,0 +1,732 @@
+//! Talking to crates.io (or the registry chosen with `--registry` and
+//! friends) and its index. All requests go through one
+//! [`ApiClient`], which sets timeouts, retries idempotent requests that failed
+//! for transient reasons and waits out rate limiting (429 `Retry-After`)
+//! instead of pacing every call with a fixed sleep.
+
+use std::fmt;
+use std::time::Duration;
+
+use color_eyre::eyre::{Result, WrapErr, bail, eyre};
+use facet::Facet;
+use facet_json::{from_str, to_string};
+use futures::{StreamExt, stream};
+use reqwest::{Client, RequestBuilder, Response, StatusCode};
+
+use crate::exit::AuthError;
+use crate::registry::{Registry, cargo_home};
+use crate::{Forge, Package, sync};
+
+const USER_AGENT: &str = "tp-trusted-publishing-setup (contact: amos@bearcove.eu)";
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+/// Attempts per request, including the first one
+const MAX_ATTEMPTS: u32 = 5;
+/// Delay before the first retry, doubled for every further one
+const BACKOFF_BASE: Duration = Duration::from_millis(500);
+/// Longest `Retry-After` we are willing to wait out; beyond that the 429 is
+/// reported as an error.
+const MAX_RETRY_AFTER: Duration = Duration::from_secs(60);
+
+pub struct ApiClient {
+    http: Client,
+    api_url: String,
+    index_url: String,
+    /// Prefix of the directories cargo keeps its copy of the index in
+    index_cache_prefix: String,
+    /// The registry's name, as [`Registry::label`] gives it
+    label: String,
+}
+
+#[derive(Facet, Debug)]
+struct IndexConfig {
+    api: Option<String>,
+}
+
+impl ApiClient {
+    /// A client for `registry`. When its API URL isn't known it is read from
+    /// the index's `config.json`, the way cargo finds it.
+    pub async fn new(registry: &Registry) -> Result<Self> {
+        let http = Client::builder()
+            .user_agent(USER_AGENT)
+            .connect_timeout(CONNECT_TIMEOUT)
+            .timeout(REQUEST_TIMEOUT)
+            .build()?;
+        let mut client = ApiClient {
+            http,
+            api_url: registry.api_url.clone().unwrap_or_default(),
+            index_url: registry.index_url.clone(),
+            index_cache_prefix: format!("{}-", registry.index_host()),
+            label: registry.label(),
+        };
+
+        if registry.api_url.is_none() {
+            let url = format!("{}config.json", client.index_url);
+            let res = client.send(|http| http.get(&url), true).await?;
+            if !res.status().is_success() {
+                let status = res.status();
+                return Err(http_error(status, format!("Could not read {}: {}", url, status)));
+            }
+            let config: IndexConfig = from_str(&res.text().await?)?;
+            let Some(api) = config.api else {
+                bail!("{} doesn't name an API URL; pass --api-url", url);
+            };
+            client.api_url = api.trim_end_matches('/').to_string();
+        }
+
+        Ok(client)
+    }
+
+    /// The registry's name, for messages
+    pub fn label(&self) -> &str {
+        &self.label
+    }
+
+    /// Sends the request built by `build`, rebuilding it for every attempt.
+    ///
+    /// A 429 is always retried after its `Retry-After`, since the request was
+    /// turned away before being acted on. Timeouts, connection failures and
+    /// 5xx responses are only retried when `idempotent` is set: a POST that
+    /// timed out may well have gone through.
+    async fn send(&self, build: impl Fn(&Client) -> RequestBuilder, idempotent: bool) -> reqwest::Result<Response> {
+        let mut attempt = 1;
+        loop {
+            let result = build(&self.http).send().await;
+
+            let delay = match &result {
+                Ok(res) if res.status() == StatusCode::TOO_MANY_REQUESTS => match retry_after(res) {
+                    Some(delay) if delay <= MAX_RETRY_AFTER => delay,
+                    Some(_) => return result,
+                    None => backoff(attempt),
+                },
+                Ok(res) if idempotent && res.status().is_server_error() => backoff(attempt),
+                Err(e) if idempotent && (e.is_timeout() || e.is_connect() || e.is_request()) => backoff(attempt),
+                _ => return result,
+            };
+
+            if attempt >= MAX_ATTEMPTS {
+                return result;
+            }
+            tokio::time::sleep(delay).await;
+            attempt += 1;
+        }
+    }
+}
+
+fn backoff(attempt: u32) -> Duration {
+    BACKOFF_BASE * 2u32.pow(attempt - 1)
+}
+
+/// The delay asked for by a `Retry-After` header, when given in seconds.
+fn retry_after(res: &Response) -> Option<Duration> {
+    let value = res.headers().get(reqwest::header::RETRY_AFTER)?.to_str().ok()?;
+    value.trim().parse().ok().map(Duration::from_secs)
+}
+
+/// Error for an unsuccessful crates.io response. A rejected token is flagged
+/// as such so it gets its own exit code.
+fn http_error(status: StatusCode, message: String) -> color_eyre::eyre::Error {
+    if status == StatusCode::UNAUTHORIZED || status == StatusCode::FORBIDDEN {
+        AuthError(message).into()
+    } else {
+        eyre!(message)
+    }
+}
+
+#[derive(Facet, Debug)]
+struct MeResponse {
+    user: CurrentUser,
+}
+
+/// The account a token belongs to, from `/api/v1/me`.
+#[derive(Facet, Debug)]
+pub struct CurrentUser {
+    pub login: String,
+    pub name: Option<String>,
+    pub email: Option<String>,
+    pub email_verified: Option<bool>,
+}
+
+impl CurrentUser {
+    /// crates.io only lets accounts with a verified email address publish.
+    pub fn can_publish(&self) -> bool {
+        self.email.is_some() && self.email_verified == Some(true)
+    }
+}
+
+#[derive(Facet, Debug)]
+struct OwnersResponse {
+    users: Vec<Owner>,
+}
+
+#[derive(Facet, Debug)]
+struct OwnersRequest {
+    owners: Vec<String>,
+}
+
+#[derive(Facet, Debug)]
+struct OwnersChangeResponse {
+    msg: Option<String>,
+}
+
+#[derive(Facet, Debug)]
+struct CrateResponse {
+    #[facet(rename = "crate")]
+    krate: CrateInfo,
+}
+
+#[derive(Facet, Debug)]
+struct CrateInfo {
+    trustpub_only: Option<bool>,
+}
+
+#[derive(Facet, Debug)]
+struct CrateUpdateRequest {
+    #[facet(rename = "crate")]
+    krate: CrateUpdate,
+}
+
+#[derive(Facet, Debug)]
+struct CrateUpdate {
+    trustpub_only: bool,
+}
+
+/// A crate owner: a user, or a team such as `github:org:team`.
+#[derive(Facet, Debug, Clone)]
+pub struct Owner {
+    pub login: String,
+    /// "user" or "team"
+    pub kind: String,
+    pub name: Option<String>,
+}
+
+impl Owner {
+    pub fn is_team(&self) -> bool {
+        self.kind == "team"
+    }
+}
+
+#[derive(Facet, Debug)]
+struct GithubConfigRequest {
+    github_config: GithubConfigInner,
+}
+
+#[derive(Facet, Debug)]
+struct GithubConfigInner {
+    #[facet(rename = "crate")]
+    crate_name: String,
+    repository_owner: String,
+    repository_name: String,
+    workflow_filename: String,
+    environment: Option<String>,
+}
+
+#[derive(Facet, Debug)]
+struct GithubConfigListResponse {
+    github_configs: Vec<GithubConfig>,
+}
+
+#[derive(Facet, Debug)]
+struct GithubConfig {
+    id: u64,
+    #[facet(rename = "crate")]
+    crate_name: String,
+    repository_owner: String,
+    repository_name: String,
+    workflow_filename: String,
+    environment: Option<String>,
+}
+
+#[derive(Facet, Debug)]
+struct GitlabConfigRequest {
+    gitlab_config: GitlabConfigInner,
+}
+
+#[derive(Facet, Debug)]
+struct GitlabConfigInner {
+    #[facet(rename = "crate")]
+    crate_name: String,
+    namespace: String,
+    project: String,
+    workflow_filepath: String,
+    environment: Option<String>,
+}
+
+#[derive(Facet, Debug)]
+struct GitlabConfigListResponse {
+    gitlab_configs: Vec<GitlabConfig>,
+}
+
+#[derive(Facet, Debug)]
+struct GitlabConfig {
+    id: u64,
+    #[facet(rename = "crate")]
+    crate_name: String,
+    namespace: String,
+    project: String,
+    workflow_filepath: String,
+    environment: Option<String>,
+}
+
+/// A trusted publishing configuration of either forge, normalized so GitHub
+/// and GitLab configurations can be listed and compared side by side. For
+/// GitLab, `owner` is the namespace, `repo` the project and `workflow` the CI
+/// file path.
+#[derive(Debug, Clone)]
+pub struct TrustpubConfig {
+    pub id: u64,
+    pub forge: Forge,
+    pub crate_name: String,
+    pub owner: String,
+    pub repo: String,
+    pub workflow: String,
+    pub environment: Option<String>,
+}
+
+impl TrustpubConfig {
+    /// "owner/repo" for GitHub, "gitlab:namespace/project" for GitLab
+    pub fn repo_label(&self) -> String {
+        match self.forge {
+            Forge::Github => format!("{}/{}", self.owner, self.repo),
+            Forge::Gitlab => format!("gitlab:{}/{}", self.owner, self.repo),
+        }
+    }
+
+    /// " [environment]" when the configuration requires one, "" otherwise
+    pub fn environment_label(&self) -> String {
+        match &self.environment {
+            Some(env) => format!(" [{}]", env),
+            None => String::new(),
+        }
+    }
+}
+
+impl From<GithubConfig> for TrustpubConfig {
+    fn from(cfg: GithubConfig) -> Self {
+        TrustpubConfig {
+            id: cfg.id,
+            forge: Forge::Github,
+            crate_name: cfg.crate_name,
+            owner: cfg.repository_owner,
+            repo: cfg.repository_name,
+            workflow: cfg.workflow_filename,
+            environment: cfg.environment,
+        }
+    }
+}
+
+impl From<GitlabConfig> for TrustpubConfig {
+    fn from(cfg: GitlabConfig) -> Self {
+        TrustpubConfig {
+            id: cfg.id,
+            forge: Forge::Gitlab,
+            crate_name: cfg.crate_name,
+            owner: cfg.namespace,
+            repo: cfg.project,
+            workflow: cfg.workflow_filepath,
+            environment: cfg.environment,
+        }
+    }
+}
+
+fn sparse_index_path(name: &str) -> String {
+    let name = name.to_lowercase();
+    match name.len() {
+        1 => format!("1/{}", name),
+        2 => format!("2/{}", name),
+        3 => format!("3/{}/{}", &name[..1], name),
+        _ => format!("{}/{}/{}", &name[..2], &name[2..4], name),
+    }
+}
+
+fn check_local_sparse_index(client: &ApiClient, name: &str) -> bool {
+    let index_dir = cargo_home().join("registry").join("index");
+
+    // Find any <index host>-* directory
+    if let Ok(entries) = std::fs::read_dir(&index_dir) {
+        for entry in entries.flatten() {
+            let dir_name = entry.file_name();
+            if dir_name.to_string_lossy().starts_with(&client.index_cache_prefix) {
+                let cache_path = entry.path().join(sparse_index_path(name));
+                if cache_path.exists() {
+                    return true;
+                }
+            }
+        }
+    }
+    false
+}
+
+/// Whether the crate is on the registry. Only a definite answer from the index
+/// counts as "no": anything else is an error, so a flaky network never makes
+/// an existing crate look unpublished.
+pub async fn crate_exists(client: &ApiClient, name: &str) -> Result<bool> {
+    // Check local cache first
+    if check_local_sparse_index(client, name) {
+        return Ok(true);
+    }
+
+    // Fall back to network request
+    let url = format!("{}{}", client.index_url, sparse_index_path(name));
+    let res = client
+        .send(|http| http.get(&url).header("Accept", "text/plain"), true)
+        .await?;
+    match res.status() {
+        status if status.is_success() => Ok(true),
+        StatusCode::NOT_FOUND | StatusCode::GONE => Ok(false),
+        // The index being down counts as a network failure for the exit code
+        status if status.is_server_error() => res
+            .error_for_status()
+            .map(|_| true)
+            .wrap_err_with(|| format!("Index lookup for {} failed: {}", name, status)),
+        status => Err(http_error(status, format!("Index lookup for {} failed: {}", name, status))),
+    }
+}
+
+/// Whether an error response means the token itself was refused, rather than
+/// the account behind it lacking a right. crates.io answers an unknown,
+/// expired or revoked token with 403 "authentication failed".
+fn token_refused(status: StatusCode, text: &str) -> bool {
+    status == StatusCode::UNAUTHORIZED
+        || (status == StatusCode::FORBIDDEN && text.to_ascii_lowercase().contains("authentication failed"))
+}
+
+fn refused_error(text: &str) -> color_eyre::eyre::Error {
+    AuthError(format!("The token was rejected; it may be expired or revoked: {}", text)).into()
+}
+
+/// Marks an error as crates.io refusing a valid token about one crate: its
+/// account doesn't own the crate, or the token's scopes leave the crate out.
+#[derive(Debug)]
+pub struct NotOwned(pub String);
+
+impl fmt::Display for NotOwned {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for NotOwned {}
+
+/// Error for an unsuccessful request about one crate's trusted publishing:
+/// a refused token, the crate not being the account's, or anything else.
+fn crate_error(status: StatusCode, text: &str, message: String) -> color_eyre::eyre::Error {
+    if token_refused(status, text) {
+        refused_error(text)
+    } else if status == StatusCode::FORBIDDEN || text.contains("not an owner") {
+        NotOwned(message).into()
+    } else {
+        http_error(status, message)
+    }
+}
+
+/// Who `token` acts as. `None` when the registry won't say: `/api/v1/me` is
+/// only for browser sessions on crates.io, so it never answers an API token
+/// there, and other registries may not have the endpoint at all. A token it
+/// rejects outright is an error.
+pub async fn current_user(client: &ApiClient, token: &str) -> Result<Option<CurrentUser>> {
+    let url = format!("{}/api/v1/me", client.api_url);
+    let res = client
+        .send(|http| http.get(&url).header("Authorization", token), true)
+        .await?;
+
+    match res.status() {
+        status if status.is_success() => {
+            let response: MeResponse = from_str(&res.text().await?)?;
+            Ok(Some(response.user))
+        }
+        status => {
+            let text = res.text().await?;
+            if token_refused(status, &text) {
+                Err(refused_error(&text))
+            } else if status == StatusCode::FORBIDDEN || status == StatusCode::NOT_FOUND {
+                Ok(None)
+            } else {
+                Err(http_error(status, format!("Could not look up the token's account: {}: {}", status, text)))
+            }
+        }
+    }
+}
+
+/// Checks that the registry accepts `token`, by listing the GitHub
+/// configurations of `crate_name`: unlike `/api/v1/me`, that endpoint takes
+/// API tokens, and it checks them before looking the crate up. Only a
+/// refused token fails; an account that doesn't own the crate, or a crate
+/// that doesn't exist yet, still leaves a valid token.
+pub async fn check_token(client: &ApiClient, token: &str, crate_name: &str) -> Result<()> {
+    let url = format!(
+        "{}/api/v1/trusted_publishing/{}?crate={}",
+        client.api_url,
+        Forge::Github.endpoint(),
+        crate_name
+    );
+    let res = client
+        .send(|http| http.get(&url).header("Authorization", token), true)
+        .await?;
+
+    let status = res.status();
+    if status.is_success() {
+        return Ok(());
+    }
+    let text = res.text().await?;
+    if token_refused(status, &text) {
+        return Err(refused_error(&text));
+    }
+    if status.is_server_error() {
+        return Err(http_error(status, format!("Could not check the token: {}: {}", status, text)));
+    }
+    Ok(())
+}
+
+/// Everyone who owns the crate, users and teams alike.
+pub async fn crate_owners(client: &ApiClient, name: &str) -> Result<Vec<Owner>> {
+    let url = format!("{}/api/v1/crates/{}/owners", client.api_url, name);
+    let res = client.send(|http| http.get(&url), true).await?;
+    if !res.status().is_success() {
+        let status = res.status();
+        let text = res.text().await?;
+        return Err(http_error(status, format!("Could not list owners of {}: {}: {}", name, status, text)));
+    }
+    let response: OwnersResponse = from_str(&res.text().await?)?;
+    Ok(response.users)
+}
+
+/// Adds owners to the crate. Teams are added right away; users are sent an
+/// invitation, and only become owners once they accept it. Returns what
+/// crates.io said about it.
+pub async fn add_owners(client: &ApiClient, token: &str, name: &str, logins: &[String]) -> Result<String> {
+    change_owners(client, token, name, logins, true).await
+}
+
+pub async fn remove_owners(client: &ApiClient, token: &str, name: &str, logins: &[String]) -> Result<String> {
+    change_owners(client, token, name, logins, false).await
+}
+
+async fn change_owners(client: &ApiClient, token: &str, name: &str, logins: &[String], add: bool) -> Result<String> {
+    let url = format!("{}/api/v1/crates/{}/owners", client.api_url, name);
+    let body = to_string(&OwnersRequest {
+        owners: logins.to_vec(),
+    })?;
+
+    // Adding sends invitation emails, so a timed-out attempt isn't repeated
+    let res = client
+        .send(
+            |http| {
+                let request = if add { http.put(&url) } else { http.delete(&url) };
+                request
+                    .header("Content-Type", "application/json")
+                    .header("Authorization", token)
+                    .body(body.clone())
+            },
+            !add,
+        )
+        .await?;
+
+    if !res.status().is_success() {
+        let status = res.status();
+        let text = res.text().await?;
+        return Err(http_error(status, format!("{}: {}", status, text)));
+    }
+    let response: OwnersChangeResponse = from_str(&res.text().await?)?;
+    Ok(response.msg.unwrap_or_default())
+}
+
+/// Whether the crate only accepts trusted publishing, refusing publishes with
+/// an API token. `None` when the registry doesn't have the setting.
+pub async fn trustpub_only(client: &ApiClient, name: &str) -> Result<Option<bool>> {
+    let url = format!("{}/api/v1/crates/{}", client.api_url, name);
+    let res = client.send(|http| http.get(&url), true).await?;
+    if !res.status().is_success() {
+        let status = res.status();
+        let text = res.text().await?;
+        return Err(http_error(status, format!("Could not look up {}: {}: {}", name, status, text)));
+    }
+    let response: CrateResponse = from_str(&res.text().await?)?;
+    Ok(response.krate.trustpub_only)
+}
+
+pub async fn set_trustpub_only(client: &ApiClient, token: &str, name: &str, enabled: bool) -> Result<()> {
+    let url = format!("{}/api/v1/crates/{}", client.api_url, name);
+    let body = to_string(&CrateUpdateRequest {
+        krate: CrateUpdate { trustpub_only: enabled },
+    })?;
+
+    // Setting a flag to the same value twice is harmless
+    let res = client
+        .send(
+            |http| {
+                http.patch(&url)
+                    .header("Content-Type", "application/json")
+                    .header("Authorization", token)
+                    .body(body.clone())
+            },
+            true,
+        )
+        .await?;
+
+    if !res.status().is_success() {
+        let status = res.status();
+        let text = res.text().await?;
+        return Err(http_error(status, format!("{}: {}", status, text)));
+    }
+    Ok(())
+}
+
+/// What listing configurations turned up.
+pub struct ConfigListing {
+    pub configs: Vec<TrustpubConfig>,
+    /// Crates whose configurations crates.io won't show the token, because its
+    /// account doesn't own them (see [`NotOwned`])
+    pub not_owned: Vec<String>,
+}
+
+/// The configurations of `crates` for each of `forges`, one request per crate
+/// and forge. A registry without the GitLab endpoint has no GitLab
+/// configurations, rather than failing the run.
+pub async fn list_trustpub_configs(
+    client: &ApiClient,
+    token: &str,
+    crates: &[Package],
+    forges: &[Forge],
+) -> Result<ConfigListing> {
+    let queries = crates
+        .iter()
+        .flat_map(|pkg| forges.iter().map(move |&forge| (forge, &pkg.name)));
+    let results: Vec<_> = stream::iter(queries.map(|(forge, crate_name)| async move {
+        (crate_name, list_configs(client, token, forge, crate_name).await)
+    }))
+    .buffer_unordered(20)
+    .collect()
+    .await;
+
+    let mut listing = ConfigListing {
+        configs: Vec::new(),
+        not_owned: Vec::new(),
+    };
+    for (crate_name, result) in results {
+        match result {
+            Ok(configs) => listing.configs.extend(configs),
+            Err(e) if e.is::<NotOwned>() => {
+                if !listing.not_owned.contains(crate_name) {
+                    listing.not_owned.push(crate_name.clone());
+                }
+            }
+            Err(e) => return Err(e),
+        }
+    }
+    listing.not_owned.sort();
+    Ok(listing)
+}
+
+async fn list_configs(client: &ApiClient, token: &str, forge: Forge, crate_name: &str) -> Result<Vec<TrustpubConfig>> {
+    let url = format!(
+        "{}/api/v1/trusted_publishing/{}?crate={}",
+        client.api_url,
+        forge.endpoint(),
+        crate_name
+    );
+
+    let res = client
+        .send(|http| http.get(&url).header("Authorization", token), true)
+        .await?;
+
+    if forge == Forge::Gitlab && res.status() == StatusCode::NOT_FOUND {
+        return Ok(Vec::new());
+    }
+    if !res.status().is_success() {
+        let status = res.status();
+        let text = res.text().await?;
+        return Err(crate_error(
+            status,
+            &text,
+            format!("Failed to list configurations for {}: {}: {}", crate_name, status, text),
+        ));
+    }
+
+    let body = res.text().await?;
+    let configs = match forge {
+        Forge::Github => {
+            let response: GithubConfigListResponse = from_str(&body)?;
+            response.github_configs.into_iter().map(Into::into).collect()
+        }
+        Forge::Gitlab => {
+            let response: GitlabConfigListResponse = from_str(&body)?;
+            response.gitlab_configs.into_iter().map(Into::into).collect()
+        }
+    };
+    Ok(configs)
+}
+
+pub async fn create_trustpub_config(
+    client: &ApiClient,
+    token: &str,
+    crate_name: &str,
+    desired: &sync::Desired<'_>,
+) -> Result<()> {
+    let Some(workflow) = desired.workflow else {
+        bail!("No workflow to configure {} for", crate_name);
+    };
+    let url = format!("{}/api/v1/trusted_publishing/{}", client.api_url, desired.forge.endpoint());
+    let body = match desired.forge {
+        Forge::Github => to_string(&GithubConfigRequest {
+            github_config: GithubConfigInner {
+                crate_name: crate_name.to_string(),
+                repository_owner: desired.owner.to_string(),
+                repository_name: desired.repo.to_string(),
+                workflow_filename: workflow.to_string(),
+                environment: desired.environment.map(str::to_string),
+            },
+        })?,
+        Forge::Gitlab => to_string(&GitlabConfigRequest {
+            gitlab_config: GitlabConfigInner {
+                crate_name: crate_name.to_string(),
+                namespace: desired.owner.to_string(),
+                project: desired.repo.to_string(),
+                workflow_filepath: workflow.to_string(),
+                environment: desired.environment.map(str::to_string),
+            },
+        })?,
+    };
+
+    let res = client
+        .send(
+            |http| {
+                http.post(&url)
+                    .header("Content-Type", "application/json")
+                    .header("Authorization", token)
+                    .body(body.clone())
+            },
+            false,
+        )
+        .await?;
+
+    if !res.status().is_success() {
+        let status = res.status();
+        let text = res.text().await?;
+        return Err(crate_error(status, &text, format!("{}: {}", status, text)));
+    }
+
+    Ok(())
+}
+
+pub async fn delete_trustpub_config(client: &ApiClient, token: &str, config: &TrustpubConfig) -> Result<()> {
+    let url = format!(
+        "{}/api/v1/trusted_publishing/{}/{}",
+        client.api_url,
+        config.forge.endpoint(),
+        config.id
+    );
+
+    let res = client
+        .send(|http| http.delete(&url).header("Authorization", token), true)
+        .await?;
+
+    if !res.status().is_success() {
+        let status = res.status();
+        let text = res.text().await?;
+        return Err(http_error(status, format!("{}: {}", status, text)));
+    }
+
+    Ok(())
+}
@@ -0,0 +1,471 @@
+//! Config files, so a team can pin what `tp` targets instead of everyone
+//! passing their own `-w`/`-e` flags. Layers, lowest precedence first:
+//!
+//! 1. the user's `~/.config/tp/config.toml`
+//! 2. the project's `tp.toml` (or `.config/tp.toml`) at the workspace root
+//! 3. environment variables, for the settings that have one
+//! 4. command-line flags
+//!
+//! Every key is optional; a layer only fills in what the layers above it
+//! left unset. `owner`, `repo`, `workflow` and `environment` also exist in
+//! `[workspace.metadata.tp]`, which outranks config files, so those four are
+//! handed over as [`Config::defaults`] to go under the workspace table rather
+//! than filled in as if they were flags.
+//!
+//! figue's own config-file layer can't be used for this: it resolves
+//! everything into the parsed arguments before the workspace is read, which
+//! is exactly what would put config files above the workspace table.
+
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use color_eyre::eyre::{Result, bail, eyre};
+use facet::Facet;
+use owo_colors::OwoColorize;
+
+use crate::TargetArgs;
+use crate::overrides::Overrides;
+use crate::registry::{self, RegistryArgs};
+use crate::select::PackageArgs;
+
+#[derive(Facet, Debug, Default)]
+struct ConfigFile {
+    owner: Option<String>,
+    repo: Option<String>,
+    workflow: Option<String>,
+    environment: Option<String>,
+    gitlab: Option<bool>,
+    #[facet(rename = "token-env")]
+    token_env: Option<String>,
+    registry: Option<String>,
+    #[facet(rename = "api-url")]
+    api_url: Option<String>,
+    #[facet(rename = "index-url")]
+    index_url: Option<String>,
+    package: Option<Vec<String>>,
+    exclude: Option<Vec<String>>,
+    #[facet(rename = "crate-owners")]
+    crate_owners: Option<Vec<String>>,
+}
+
+struct Layer {
+    /// Where the layer was read from, for telling the user
+    path: PathBuf,
+    file: ConfigFile,
+}
+
+/// The config files found, highest precedence first.
+pub struct Config {
+    layers: Vec<Layer>,
+}
+
+/// Where an effective setting came from.
+pub struct Origin {
+    key: &'static str,
+    value: String,
+    source: String,
+}
+
+impl Config {
+    pub fn load() -> Result<Self> {
+        let mut layers = Vec::new();
+        if let Some(path) = project_config_path() {
+            layers.push(Layer::load(path)?);
+        }
+        if let Some(path) = user_config_path().filter(|p| p.is_file()) {
+            layers.push(Layer::load(path)?);
+        }
+        Ok(Config { layers })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.layers.is_empty()
+    }
+
+    /// Fills in whatever the command line left unset in the options of
+    /// `setup`, `sync`, `status` and `workflow init`, except those
+    /// [`Config::defaults`] covers.
+    pub fn apply_target(&self, target: &mut TargetArgs, origins: &mut Vec<Origin>) {
+        self.fill_token_env(&mut target.token_env, origins);
+
+        // A flag can only turn it on, so `false` means "not given"
+        let gitlab = if target.gitlab {
+            Some((true, "command line".to_string()))
+        } else {
+            self.find(|f| f.gitlab.as_ref()).map(|(&gitlab, source)| (gitlab, source))
+        };
+        if let Some((gitlab, source)) = gitlab {
+            target.gitlab = gitlab;
+            origins.push(Origin {
+                key: "gitlab",
+                value: gitlab.to_string(),
+                source,
+            });
+        }
+
+        self.apply_packages(&mut target.packages, origins);
+    }
+
+    /// The repository, workflow and environment the config files set, to
+    /// use wherever neither the command line nor `[workspace.metadata.tp]`
+    /// sets them. Whatever `target` already has is only recorded.
+    pub fn defaults(&self, target: &TargetArgs, origins: &mut Vec<Origin>) -> Result<Overrides> {
+        let mut owner = target.owner.clone();
+        let mut repo = target.repo.clone();
+        self.fill("owner", &mut owner, None, |f| f.owner.as_ref(), origins);
+        self.fill("repo", &mut repo, None, |f| f.repo.as_ref(), origins);
+        let repository = match (owner, repo) {
+            _ if target.owner.is_some() || target.repo.is_some() => None,
+            (Some(owner), Some(repo)) => Some(format!("{}/{}", owner, repo)),
+            (None, None) => None,
+            _ => bail!("`owner` and `repo` go together in config files; set both or neither"),
+        };
+
+        let mut workflow = target.workflow.clone();
+        let mut environment = target.environment.clone();
+        self.fill_workflow(&mut workflow, origins);
+        self.fill_environment(target, &mut environment, origins);
+        Ok(Overrides {
+            repository,
+            workflow: workflow.filter(|_| target.workflow.is_none()),
+            environment: environment.filter(|_| target.environment.is_none()),
+            ..Overrides::default()
+        })
+    }
+
+    /// Like [`Config::fill`], except that an environment belongs to the
+    /// workflow whose job runs in it: a layer's is only used when no higher
+    /// layer, the command line included, picks the workflow.
+    fn fill_environment(&self, target: &TargetArgs, environment: &mut Option<String>, origins: &mut Vec<Origin>) {
+        let reach = match target.workflow {
+            Some(_) => 0,
+            None => self
+                .layers
+                .iter()
+                .position(|layer| layer.file.workflow.is_some())
+                .map_or(self.layers.len(), |i| i + 1),
+        };
+        let (value, source) = if let Some(value) = environment.take() {
+            (value, "command line".to_string())
+        } else if let Some(layer) = self.layers[..reach].iter().find(|layer| layer.file.environment.is_some()) {
+            (layer.file.environment.clone().unwrap_or_default(), layer.path.display().to_string())
+        } else {
+            return;
+        };
+
+        origins.push(Origin {
+            key: "environment",
+            value: value.clone(),
+            source,
+        });
+        *environment = Some(value);
+    }
+
+    pub fn apply_registry(&self, registry: &mut RegistryArgs, origins: &mut Vec<Origin>) {
+        let RegistryArgs {
+            registry,
+            api_url,
+            index_url,
+        } = registry;
+        self.fill("registry", registry, Some("TP_REGISTRY"), |f| f.registry.as_ref(), origins);
+        self.fill("api-url", api_url, Some("TP_API_URL"), |f| f.api_url.as_ref(), origins);
+        self.fill("index-url", index_url, Some("TP_INDEX_URL"), |f| f.index_url.as_ref(), origins);
+    }
+
+    pub fn fill_token_env(&self, token_env: &mut Option<String>, origins: &mut Vec<Origin>) {
+        self.fill("token-env", token_env, None, |f| f.token_env.as_ref(), origins);
+    }
+
+    pub fn fill_workflow(&self, workflow: &mut Option<String>, origins: &mut Vec<Origin>) {
+        self.fill("workflow", workflow, None, |f| f.workflow.as_ref(), origins);
+    }
+
+    /// The owners `tp owners` gives every crate.
+    pub fn apply_crate_owners(&self, crate_owners: &mut Vec<String>, origins: &mut Vec<Origin>) {
+        let mut owners = Some(std::mem::take(crate_owners)).filter(|o| !o.is_empty());
+        self.fill_list("crate-owners", &mut owners, |f| f.crate_owners.as_ref(), origins);
+        *crate_owners = owners.unwrap_or_default();
+    }
+
+    pub fn apply_packages(&self, packages: &mut PackageArgs, origins: &mut Vec<Origin>) {
+        // An empty list means the option wasn't given
+        let mut package = Some(std::mem::take(&mut packages.package)).filter(|p| !p.is_empty());
+        let mut exclude = Some(std::mem::take(&mut packages.exclude)).filter(|p| !p.is_empty());
+        self.fill_list("package", &mut package, |f| f.package.as_ref(), origins);
+        self.fill_list("exclude", &mut exclude, |f| f.exclude.as_ref(), origins);
+        packages.package = package.unwrap_or_default();
+        packages.exclude = exclude.unwrap_or_default();
+    }
+
+    /// Sets `slot` from the first of: the command line (already in `slot`),
+    /// the environment variable `env`, then each config layer.
+    fn fill(
+        &self,
+        key: &'static str,
+        slot: &mut Option<String>,
+        env: Option<&str>,
+        get: impl Fn(&ConfigFile) -> Option<&String>,
+        origins: &mut Vec<Origin>,
+    ) {
+        let (value, source) = if let Some(value) = slot.take() {
+            (value, "command line".to_string())
+        } else if let Some((var, value)) = env.and_then(|var| Some((var, registry::env(var)?))) {
+            (value, var.to_string())
+        } else if let Some((value, source)) = self.find(get) {
+            (value.clone(), source)
+        } else {
+            return;
+        };
+
+        origins.push(Origin {
+            key,
+            value: value.clone(),
+            source,
+        });
+        *slot = Some(value);
+    }
+
+    fn fill_list(
+        &self,
+        key: &'static str,
+        slot: &mut Option<Vec<String>>,
+        get: impl Fn(&ConfigFile) -> Option<&Vec<String>>,
+        origins: &mut Vec<Origin>,
+    ) {
+        let (value, source) = if let Some(value) = slot.take() {
+            (value, "command line".to_string())
+        } else if let Some((value, source)) = self.find(get) {
+            (value.clone(), source)
+        } else {
+            return;
+        };
+
+        origins.push(Origin {
+            key,
+            value: value.join(", "),
+            source,
+        });
+        *slot = Some(value);
+    }
+
+    /// The value from the highest layer that sets it, and that layer's path.
+    fn find<'a, T>(&'a self, get: impl Fn(&'a ConfigFile) -> Option<&'a T>) -> Option<(&'a T, String)> {
+        self.layers
+            .iter()
+            .find_map(|layer| Some((get(&layer.file)?, layer.path.display().to_string())))
+    }
+}
+
+impl Layer {
+    fn load(path: PathBuf) -> Result<Self> {
+        let contents =
+            std::fs::read_to_string(&path).map_err(|e| eyre!("Could not read {}: {}", path.display(), e))?;
+        let file = facet_toml::from_str(&contents).map_err(|e| eyre!("Could not parse {}: {}", path.display(), e))?;
+        Ok(Layer { path, file })
+    }
+}
+
+/// Prints each effective setting and the layer it came from.
+pub fn print_origins(origins: &[Origin]) {
+    if origins.is_empty() {
+        return;
+    }
+
+    let key_width = origins.iter().map(|o| o.key.len()).max().unwrap_or(0);
+    say!("{}", "🧾 Settings:".cyan());
+    for origin in origins {
+        say!(
+            "   {}  {} {}",
+            format!("{:<key_width$}", origin.key).dimmed(),
+            origin.value.green(),
+            format!("({})", origin.source).dimmed()
+        );
+    }
+    say!();
+}
+
+/// `tp.toml` or `.config/tp.toml` in the workspace root, whichever exists.
+fn project_config_path() -> Option<PathBuf> {
+    let root = workspace_root().or_else(|| std::env::current_dir().ok())?;
+    [root.join("tp.toml"), root.join(".config").join("tp.toml")]
+        .into_iter()
+        .find(|p| p.is_file())
+}
+
+fn workspace_root() -> Option<PathBuf> {
+    let output = Command::new("cargo")
+        .args(["locate-project", "--workspace", "--message-format", "plain"])
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let manifest = String::from_utf8(output.stdout).ok()?;
+    Path::new(manifest.trim()).parent().map(Path::to_path_buf)
+}
+
+fn user_config_path() -> Option<PathBuf> {
+    let config_home = std::env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| dirs::home_dir().map(|home| home.join(".config")))?;
+    Some(config_home.join("tp").join("config.toml"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(layers: Vec<(&str, ConfigFile)>) -> Config {
+        Config {
+            layers: layers
+                .into_iter()
+                .map(|(path, file)| Layer {
+                    path: PathBuf::from(path),
+                    file,
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn project_file_beats_user_file() {
+        let config = config(vec![
+            (
+                "tp.toml",
+                ConfigFile {
+                    workflow: Some("release.yml".to_string()),
+                    ..ConfigFile::default()
+                },
+            ),
+            (
+                "~/.config/tp/config.toml",
+                ConfigFile {
+                    owner: Some("me".to_string()),
+                    repo: Some("fork".to_string()),
+                    workflow: Some("mine.yml".to_string()),
+                    environment: Some("mine".to_string()),
+                    ..ConfigFile::default()
+                },
+            ),
+        ]);
+
+        let mut origins = Vec::new();
+        let defaults = config.defaults(&TargetArgs::default(), &mut origins).unwrap();
+        assert_eq!(defaults.repository.as_deref(), Some("me/fork"));
+        assert_eq!(defaults.workflow.as_deref(), Some("release.yml"));
+        // The user file's environment was meant for mine.yml
+        assert_eq!(defaults.environment, None);
+        let workflow = origins.iter().find(|o| o.key == "workflow").unwrap();
+        assert_eq!(workflow.source, "tp.toml");
+    }
+
+    #[test]
+    fn environment_goes_with_its_workflow() {
+        let config = config(vec![(
+            "tp.toml",
+            ConfigFile {
+                workflow: Some("release.yml".to_string()),
+                environment: Some("release".to_string()),
+                ..ConfigFile::default()
+            },
+        )]);
+
+        // `-w` picks another workflow, so tp.toml's environment doesn't apply
+        let target = TargetArgs {
+            workflow: Some("ci.yml".to_string()),
+            ..TargetArgs::default()
+        };
+        let mut origins = Vec::new();
+        let defaults = config.defaults(&target, &mut origins).unwrap();
+        let chosen = target.over(&Overrides::default().or(&defaults));
+        assert_eq!(chosen.workflow.as_deref(), Some("ci.yml"));
+        assert_eq!(chosen.environment, None);
+        assert!(origins.iter().all(|o| o.key != "environment"));
+
+        // Nor does [workspace.metadata.tp]'s
+        let workspace = Overrides {
+            workflow: Some("release.yml".to_string()),
+            environment: Some("release".to_string()),
+            ..Overrides::default()
+        };
+        assert_eq!(target.over(&workspace).environment, None);
+
+        // Given with it, the environment is kept
+        let target = TargetArgs {
+            workflow: Some("ci.yml".to_string()),
+            environment: Some("ci".to_string()),
+            ..TargetArgs::default()
+        };
+        assert_eq!(target.over(&workspace).environment.as_deref(), Some("ci"));
+        // And without `-w`, the lower layer's pair stays whole
+        assert_eq!(TargetArgs::default().over(&workspace).environment.as_deref(), Some("release"));
+    }
+
+    #[test]
+    fn workspace_table_beats_config_files() {
+        let config = config(vec![(
+            "tp.toml",
+            ConfigFile {
+                owner: Some("facet-rs".to_string()),
+                repo: Some("facet".to_string()),
+                workflow: Some("release.yml".to_string()),
+                environment: Some("release".to_string()),
+                ..ConfigFile::default()
+            },
+        )]);
+        let defaults = config.defaults(&TargetArgs::default(), &mut Vec::new()).unwrap();
+
+        // The workspace picks its own workflow, so the config file's
+        // environment (meant for release.yml) goes with it
+        let workspace = Overrides {
+            workflow: Some("publish.yml".to_string()),
+            ..Overrides::default()
+        };
+        let effective = workspace.or(&defaults);
+        assert_eq!(effective.workflow.as_deref(), Some("publish.yml"));
+        assert_eq!(effective.environment, None);
+        assert_eq!(effective.repository.as_deref(), Some("facet-rs/facet"));
+
+        // Without a workspace table, the config file applies as a whole
+        let effective = Overrides::default().or(&defaults);
+        assert_eq!(effective.workflow.as_deref(), Some("release.yml"));
+        assert_eq!(effective.environment.as_deref(), Some("release"));
+    }
+
+    #[test]
+    fn command_line_beats_config_files() {
+        let config = config(vec![(
+            "tp.toml",
+            ConfigFile {
+                owner: Some("facet-rs".to_string()),
+                repo: Some("facet".to_string()),
+                workflow: Some("release.yml".to_string()),
+                ..ConfigFile::default()
+            },
+        )]);
+        let target = TargetArgs {
+            owner: Some("me".to_string()),
+            repo: Some("fork".to_string()),
+            workflow: Some("ci.yml".to_string()),
+            ..TargetArgs::default()
+        };
+
+        let mut origins = Vec::new();
+        let defaults = config.defaults(&target, &mut origins).unwrap();
+        assert_eq!(defaults.repository, None);
+        assert_eq!(defaults.workflow, None);
+        assert!(origins.iter().all(|o| o.source == "command line"));
+    }
+
+    #[test]
+    fn owner_needs_repo() {
+        let config = config(vec![(
+            "tp.toml",
+            ConfigFile {
+                owner: Some("facet-rs".to_string()),
+                ..ConfigFile::default()
+            },
+        )]);
+        assert!(config.defaults(&TargetArgs::default(), &mut Vec::new()).is_err());
+    }
+}
@@ -0,0 +1,620 @@
+//! Finding the registry token the way cargo does, so `tp` works wherever
+//! `cargo publish` does. Cargo asks a list of credential providers in turn;
+//! the default, `cargo:token`, reads the `CARGO_REGISTRY_TOKEN` or
+//! `CARGO_REGISTRIES_<NAME>_TOKEN` environment variable, then the
+//! credentials file in `$CARGO_HOME`, then cargo's config files. Other
+//! providers are external programs speaking cargo's credential-provider
+//! JSON protocol, or built-ins `tp` mimics.
+
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+use color_eyre::eyre::{Result, WrapErr, bail, eyre};
+use facet::Facet;
+use facet_json::{from_str, to_string};
+use owo_colors::OwoColorize;
+
+use crate::registry::{CRATES_IO, CargoConfig, ProviderCommand, Registry, cargo_config_files, cargo_home, env, env_key};
+
+/// Index URL cargo hands credential providers for crates.io, whatever
+/// protocol it fetches the index with
+const CRATES_IO_INDEX_URL: &str = "https://github.com/rust-lang/crates.io-index";
+
+pub struct Token {
+    pub secret: String,
+    /// Where the token was found: an environment variable, a file path or a
+    /// credential provider
+    pub source: String,
+}
+
+/// A credential provider to ask, with the directory relative paths in its
+/// command are resolved against.
+struct Provider {
+    args: Vec<String>,
+    base: Option<PathBuf>,
+}
+
+/// Finds the token for `registry`. `token_env` names a variable that takes
+/// precedence over everything cargo would look at.
+pub fn find_token(token_env: Option<&str>, registry: &Registry) -> Result<Token> {
+    if let Some(var) = token_env {
+        let secret = std::env::var(var).map_err(|_| eyre!("Set {} environment variable", var))?;
+        return Ok(Token {
+            secret,
+            source: var.to_string(),
+        });
+    }
+
+    // crates.io's token is under [registry], other registries' under
+    // [registries.<name>]. A registry only given by URL has no entry.
+    let name = match &registry.name {
+        _ if registry.is_crates_io() => None,
+        Some(name) => Some(name.as_str()),
+        None => bail!(
+            "No credentials are stored for {}; pass --registry with its name in cargo's config, or -e",
+            registry.label()
+        ),
+    };
+
+    let configs = load_configs()?;
+    let mut searched = Vec::new();
+    if let Some(token) = ask_providers(providers(name, &configs)?, name, &configs, registry, &mut searched)? {
+        return Ok(token);
+    }
+
+    bail!(
+        "No token for {} found (looked in {}). Run `cargo login`, or pass -e <VAR> to read it from another variable.",
+        registry.label(),
+        searched.join(", ")
+    )
+}
+
+/// Asks `providers` in turn until one has the token, like cargo: a provider
+/// without one for this registry passes to the next. Records what was asked
+/// in `searched`.
+fn ask_providers(
+    providers: Vec<Provider>,
+    name: Option<&str>,
+    configs: &[(PathBuf, CargoConfig)],
+    registry: &Registry,
+    searched: &mut Vec<String>,
+) -> Result<Option<Token>> {
+    for provider in providers {
+        let label = provider.args.join(" ");
+        let found = match provider.args[0].as_str() {
+            "cargo:token" => {
+                if let Some(token) = cargo_token(name, configs, searched)? {
+                    return Ok(Some(token));
+                }
+                continue;
+            }
+            "cargo:token-from-stdout" => token_from_stdout(&provider, name, registry)?,
+            "cargo:libsecret" => libsecret(registry)?,
+            "cargo:macos-keychain" => macos_keychain(registry)?,
+            builtin if builtin.starts_with("cargo:") => {
+                eprintln!(
+                    "{} `tp` can't read tokens from {}; skipping it",
+                    "⚠️  Warning:".yellow(),
+                    builtin
+                );
+                None
+            }
+            _ => run_provider(&provider, name, registry)?,
+        };
+        match found {
+            Some(secret) => {
+                return Ok(Some(Token {
+                    secret,
+                    source: format!("credential provider `{}`", label),
+                }));
+            }
+            None => searched.push(label),
+        }
+    }
+    Ok(None)
+}
+
+/// Cargo's config files that exist, highest precedence first.
+fn load_configs() -> Result<Vec<(PathBuf, CargoConfig)>> {
+    let mut configs = Vec::new();
+    for path in cargo_config_files() {
+        if let Some(config) = CargoConfig::load(&path)? {
+            configs.push((path, config));
+        }
+    }
+    Ok(configs)
+}
+
+/// The providers to ask for the registry's token, in the order cargo asks
+/// them: the registry's own `credential-provider` alone if it has one,
+/// otherwise `registry.global-credential-providers` from last to first.
+/// Without either, just `cargo:token`.
+fn providers(name: Option<&str>, configs: &[(PathBuf, CargoConfig)]) -> Result<Vec<Provider>> {
+    let var = match name {
+        None => "CARGO_REGISTRY_CREDENTIAL_PROVIDER".to_string(),
+        Some(name) => format!("CARGO_REGISTRIES_{}_CREDENTIAL_PROVIDER", env_key(name)),
+    };
+    let own = match env(&var) {
+        Some(line) => Some((ProviderCommand::Line(line), None)),
+        None => configs.iter().find_map(|(path, config)| {
+            let command = config.registry_ref(name)?.credential_provider.clone()?;
+            Some((command, Some(path.as_path())))
+        }),
+    };
+    if let Some((command, path)) = own {
+        return Ok(vec![resolve_alias(command, path, configs)?]);
+    }
+
+    // Lists from several files add up, the more specific file's entries last
+    let mut global: Vec<(ProviderCommand, Option<&Path>)> = match env("CARGO_REGISTRY_GLOBAL_CREDENTIAL_PROVIDERS") {
+        Some(list) => list
+            .split_whitespace()
+            .map(|p| (ProviderCommand::Line(p.to_string()), None))
+            .collect(),
+        None => configs
+            .iter()
+            .rev()
+            .flat_map(|(path, config)| {
+                let list = config.registry.as_ref().and_then(|r| r.global_credential_providers.clone());
+                list.unwrap_or_default()
+                    .into_iter()
+                    .map(move |line| (ProviderCommand::Line(line), Some(path.as_path())))
+            })
+            .collect(),
+    };
+    if global.is_empty() {
+        global.push((ProviderCommand::Line("cargo:token".to_string()), None));
+    }
+
+    global
+        .into_iter()
+        .rev()
+        .map(|(command, path)| resolve_alias(command, path, configs))
+        .collect()
+}
+
+/// Expands a `[credential-alias]` name used as the provider's first word.
+/// `path` is the config file the provider was set in, if any.
+fn resolve_alias(command: ProviderCommand, path: Option<&Path>, configs: &[(PathBuf, CargoConfig)]) -> Result<Provider> {
+    let mut args = command.args();
+    if args.is_empty() {
+        bail!("Empty credential provider in {}", path.map_or("the environment".into(), |p| p.display().to_string()));
+    }
+
+    let mut path = path;
+    let alias = configs.iter().find_map(|(alias_path, config)| {
+        let alias = config.credential_alias.as_ref()?.get(&args[0])?;
+        Some((alias.args(), alias_path.as_path()))
+    });
+    if let Some((expanded, alias_path)) = alias {
+        args.splice(0..1, expanded);
+        path = Some(alias_path);
+        if args.is_empty() {
+            bail!("Empty credential alias in {}", alias_path.display());
+        }
+    }
+
+    // Like cargo, a relative path in a config file is relative to the
+    // directory holding that file's `.cargo` directory.
+    let base = path.and_then(Path::parent).and_then(Path::parent).map(Path::to_path_buf);
+    Ok(Provider { args, base })
+}
+
+/// `cargo:token`: the environment variable, the credentials file, then
+/// cargo's config files. Records each place looked in `searched`.
+fn cargo_token(
+    name: Option<&str>,
+    configs: &[(PathBuf, CargoConfig)],
+    searched: &mut Vec<String>,
+) -> Result<Option<Token>> {
+    let var = match name {
+        None => "CARGO_REGISTRY_TOKEN".to_string(),
+        Some(name) => format!("CARGO_REGISTRIES_{}_TOKEN", env_key(name)),
+    };
+    if let Some(secret) = env(&var) {
+        return Ok(Some(Token { secret, source: var }));
+    }
+    searched.push(var);
+
+    if let Some(path) = credentials_file() {
+        let token = CargoConfig::load(&path)?.and_then(|config| config.registry(name)?.token);
+        if let Some(secret) = token {
+            return Ok(Some(Token {
+                secret,
+                source: path.display().to_string(),
+            }));
+        }
+        searched.push(path.display().to_string());
+    }
+
+    for (path, config) in configs {
+        if let Some(secret) = config.registry_ref(name).and_then(|r| r.token.clone()) {
+            return Ok(Some(Token {
+                secret,
+                source: path.display().to_string(),
+            }));
+        }
+        searched.push(path.display().to_string());
+    }
+    Ok(None)
+}
+
+/// `$CARGO_HOME/credentials.toml`, or the legacy `credentials`, which cargo
+/// prefers when both exist.
+fn credentials_file() -> Option<PathBuf> {
+    let home = cargo_home();
+    let legacy = home.join("credentials");
+    let current = home.join("credentials.toml");
+    match (legacy.is_file(), current.is_file()) {
+        (true, true) => {
+            eprintln!(
+                "{} both {} and {} exist; using {}, like cargo",
+                "⚠️  Warning:".yellow(),
+                legacy.display(),
+                current.display(),
+                legacy.display()
+            );
+            Some(legacy)
+        }
+        (true, false) => Some(legacy),
+        (false, true) => Some(current),
+        (false, false) => None,
+    }
+}
+
+/// The index URL cargo identifies the registry by when talking to providers.
+fn index_url(registry: &Registry) -> String {
+    if registry.is_crates_io() {
+        CRATES_IO_INDEX_URL.to_string()
+    } else {
+        format!("sparse+{}", registry.index_url)
+    }
+}
+
+/// Resolves a provider's program the way cargo does: a bare name is looked
+/// up in `PATH`, a relative path is taken from where it was configured.
+fn program(provider: &Provider, program: &str) -> PathBuf {
+    let path = Path::new(program);
+    match &provider.base {
+        Some(base) if path.is_relative() && path.components().count() > 1 => base.join(path),
+        _ => path.to_path_buf(),
+    }
+}
+
+/// `cargo:token-from-stdout <command> [args...]`: the first line the command
+/// prints is the token.
+fn token_from_stdout(provider: &Provider, name: Option<&str>, registry: &Registry) -> Result<Option<String>> {
+    let Some(command) = provider.args.get(1) else {
+        bail!("cargo:token-from-stdout needs a command to run");
+    };
+    let output = Command::new(program(provider, command))
+        .args(&provider.args[2..])
+        .env("CARGO_REGISTRY_INDEX_URL", index_url(registry))
+        .env("CARGO_REGISTRY_NAME_OPT", name.unwrap_or(CRATES_IO))
+        .stderr(Stdio::inherit())
+        .output()
+        .wrap_err_with(|| format!("Could not run {}", command))?;
+    if !output.status.success() {
+        bail!("{} failed ({})", provider.args[1..].join(" "), output.status);
+    }
+
+    let stdout = String::from_utf8(output.stdout).map_err(|_| eyre!("{} printed a token that isn't UTF-8", command))?;
+    let token = stdout.strip_suffix('\n').unwrap_or(&stdout);
+    let token = token.strip_suffix('\r').unwrap_or(token);
+    if token.is_empty() {
+        bail!("{} printed no token", command);
+    }
+    if token.contains('\n') {
+        bail!("{} printed more than one line; the token must be alone on the first", command);
+    }
+    Ok(Some(token.to_string()))
+}
+
+/// `cargo:libsecret`, through `secret-tool` since the secret service has no
+/// simpler way in. Cargo stores the token under the index URL.
+fn libsecret(registry: &Registry) -> Result<Option<String>> {
+    let output = Command::new("secret-tool").args(["lookup", "url", &index_url(registry)]).output();
+    let Ok(output) = output else {
+        eprintln!(
+            "{} cargo:libsecret needs `secret-tool` (from libsecret-tools) to be installed; skipping it",
+            "⚠️  Warning:".yellow()
+        );
+        return Ok(None);
+    };
+    let token = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    Ok(Some(token).filter(|t| output.status.success() && !t.is_empty()))
+}
+
+/// `cargo:macos-keychain`, through `security`. Cargo stores the token as a
+/// generic password for the service `cargo-registry:<index URL>`.
+fn macos_keychain(registry: &Registry) -> Result<Option<String>> {
+    let service = format!("cargo-registry:{}", index_url(registry));
+    let output = Command::new("security")
+        .args(["find-generic-password", "-s", &service, "-w"])
+        .output();
+    let Ok(output) = output else {
+        return Ok(None);
+    };
+    let token = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    Ok(Some(token).filter(|t| output.status.success() && !t.is_empty()))
+}
+
+// Cargo's credential-provider protocol, version 1: the provider says hello
+// with the versions it speaks, gets one request per line on stdin, answers
+// each with one line on stdout, and exits when stdin closes.
+
+#[derive(Facet, Debug)]
+struct Hello {
+    v: Vec<u32>,
+}
+
+#[derive(Facet, Debug)]
+struct Request {
+    v: u32,
+    registry: RegistryInfo,
+    kind: String,
+    operation: String,
+    args: Vec<String>,
+}
+
+#[derive(Facet, Debug)]
+struct RegistryInfo {
+    #[facet(rename = "index-url")]
+    index_url: String,
+    name: Option<String>,
+}
+
+#[derive(Facet, Debug)]
+struct Response {
+    #[facet(rename = "Ok")]
+    ok: Option<Success>,
+    #[facet(rename = "Err")]
+    err: Option<Failure>,
+}
+
+#[derive(Facet, Debug)]
+struct Success {
+    token: Option<String>,
+}
+
+#[derive(Facet, Debug)]
+struct Failure {
+    kind: String,
+    message: Option<String>,
+    #[facet(rename = "caused-by")]
+    caused_by: Option<Vec<String>>,
+}
+
+/// Asks an external provider for the token. `None` when it has none for
+/// this registry, so the next provider gets a turn.
+fn run_provider(provider: &Provider, name: Option<&str>, registry: &Registry) -> Result<Option<String>> {
+    let command = &provider.args[0];
+    let mut child = Command::new(program(provider, command))
+        .arg("--cargo-plugin")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::inherit())
+        .spawn()
+        .wrap_err_with(|| format!("Could not run credential provider {}", command))?;
+    let mut stdin = child.stdin.take().expect("stdin is piped");
+    let mut stdout = BufReader::new(child.stdout.take().expect("stdout is piped"));
+
+    let mut line = String::new();
+    stdout.read_line(&mut line)?;
+    let hello: Hello = from_str(line.trim())
+        .map_err(|e| eyre!("Credential provider {} sent a bad greeting ({}): {}", command, e, line.trim()))?;
+    if !hello.v.contains(&1) {
+        bail!("Credential provider {} speaks protocol versions {:?}; `tp` only speaks 1", command, hello.v);
+    }
+
+    let request = Request {
+        v: 1,
+        registry: RegistryInfo {
+            index_url: index_url(registry),
+            name: Some(name.unwrap_or(CRATES_IO).to_string()),
+        },
+        kind: "get".to_string(),
+        operation: "read".to_string(),
+        args: provider.args[1..].to_vec(),
+    };
+    writeln!(stdin, "{}", to_string(&request)?)?;
+    stdin.flush()?;
+
+    line.clear();
+    stdout.read_line(&mut line)?;
+    // Closing stdin tells the provider we're done
+    drop(stdin);
+    child.wait()?;
+
+    let response: Response = from_str(line.trim())
+        .map_err(|e| eyre!("Credential provider {} sent a bad response ({}): {}", command, e, line.trim()))?;
+    match (response.ok, response.err) {
+        (Some(Success { token: Some(token) }), _) => Ok(Some(token)),
+        (Some(_), _) => bail!("Credential provider {} answered without a token", command),
+        (None, Some(failure)) if failure.kind == "not-found" || failure.kind == "url-not-supported" => Ok(None),
+        (None, Some(failure)) => {
+            let mut message = failure.message.unwrap_or(failure.kind);
+            for cause in failure.caused_by.unwrap_or_default() {
+                message.push_str(&format!(": {}", cause));
+            }
+            bail!("Credential provider {} failed: {}", command, message)
+        }
+        (None, None) => bail!("Credential provider {} sent an empty response", command),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::registry::RegistryConfig;
+
+    fn config(global: &[&str]) -> CargoConfig {
+        CargoConfig {
+            registry: Some(RegistryConfig {
+                index: None,
+                token: None,
+                credential_provider: None,
+                global_credential_providers: Some(global.iter().map(|p| p.to_string()).collect()),
+            }),
+            registries: None,
+            credential_alias: None,
+        }
+    }
+
+    fn args(providers: &[Provider]) -> Vec<Vec<&str>> {
+        providers.iter().map(|p| p.args.iter().map(String::as_str).collect()).collect()
+    }
+
+    #[test]
+    fn global_providers_are_asked_last_first() {
+        // Highest precedence first, as load_configs returns them
+        let configs = vec![
+            (PathBuf::from("/work/repo/.cargo/config.toml"), config(&["repo-provider"])),
+            (PathBuf::from("/home/me/.cargo/config.toml"), config(&["first", "second --flag"])),
+        ];
+        let providers = providers(None, &configs).unwrap();
+        assert_eq!(args(&providers), [vec!["repo-provider"], vec!["second", "--flag"], vec!["first"]]);
+    }
+
+    #[test]
+    fn registry_provider_replaces_the_global_list() {
+        let mut home = config(&["cargo:token"]);
+        home.registries = Some(HashMap::from([(
+            "private".to_string(),
+            RegistryConfig {
+                index: Some("sparse+https://crates.example.com/".to_string()),
+                token: None,
+                credential_provider: Some(ProviderCommand::Args(vec!["my vault".to_string(), "--ro".to_string()])),
+                global_credential_providers: None,
+            },
+        )]));
+        let configs = vec![(PathBuf::from("/home/me/.cargo/config.toml"), home)];
+
+        assert_eq!(args(&providers(Some("private"), &configs).unwrap()), [vec!["my vault", "--ro"]]);
+        assert_eq!(args(&providers(None, &configs).unwrap()), [vec!["cargo:token"]]);
+    }
+
+    #[test]
+    fn aliases_expand_in_place() {
+        let mut home = config(&["vault --account me", "cargo:token"]);
+        home.credential_alias = Some(HashMap::from([(
+            "vault".to_string(),
+            ProviderCommand::Line("tools/vault-provider --quiet".to_string()),
+        )]));
+        let configs = vec![(PathBuf::from("/home/me/.cargo/config.toml"), home)];
+
+        let providers = providers(None, &configs).unwrap();
+        assert_eq!(
+            args(&providers),
+            [vec!["cargo:token"], vec!["tools/vault-provider", "--quiet", "--account", "me"]]
+        );
+        // Relative to the directory holding the alias's `.cargo`
+        assert_eq!(providers[1].base.as_deref(), Some(Path::new("/home/me")));
+    }
+
+    #[test]
+    fn no_providers_means_cargo_token() {
+        let configs = vec![(PathBuf::from("/home/me/.cargo/config.toml"), config(&[]))];
+        assert_eq!(args(&providers(None, &configs).unwrap()), [vec!["cargo:token"]]);
+    }
+
+    #[cfg(unix)]
+    mod protocol {
+        use std::os::unix::fs::PermissionsExt;
+
+        use super::super::*;
+
+        fn crates_io() -> Registry {
+            Registry {
+                name: None,
+                api_url: Some("https://crates.io".to_string()),
+                index_url: "https://index.crates.io/".to_string(),
+            }
+        }
+
+        /// A fresh directory for one test's scripts.
+        fn scratch(test: &str) -> PathBuf {
+            let dir = std::env::temp_dir().join(format!("tp-credentials-{}-{}", std::process::id(), test));
+            let _ = std::fs::remove_dir_all(&dir);
+            std::fs::create_dir_all(&dir).unwrap();
+            dir
+        }
+
+        /// A provider that greets with protocol version 1, saves the
+        /// request it gets and its own arguments next to itself, and
+        /// answers with `response`.
+        fn script(dir: &Path, name: &str, response: &str) -> Provider {
+            let path = dir.join(name);
+            let body = format!(
+                "#!/bin/sh\necho \"$@\" > \"$0.argv\"\necho '{{\"v\":[1]}}'\nread request\necho \"$request\" > \"$0.request\"\necho '{}'\n",
+                response
+            );
+            std::fs::write(&path, body).unwrap();
+            std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+            Provider {
+                args: vec![path.display().to_string(), "--account".to_string(), "me".to_string()],
+                base: None,
+            }
+        }
+
+        #[test]
+        fn speaks_version_1() {
+            let dir = scratch("handshake");
+            let provider = script(
+                &dir,
+                "ok",
+                r#"{"Ok":{"kind":"get","token":"s3cret","cache":"session","operation_independent":true}}"#,
+            );
+
+            let token = run_provider(&provider, None, &crates_io()).unwrap();
+            assert_eq!(token.as_deref(), Some("s3cret"));
+
+            let argv = std::fs::read_to_string(dir.join("ok.argv")).unwrap();
+            assert_eq!(argv.trim(), "--cargo-plugin");
+            let request = std::fs::read_to_string(dir.join("ok.request")).unwrap();
+            for part in [
+                r#""v":1"#,
+                r#""kind":"get""#,
+                r#""operation":"read""#,
+                r#""args":["--account","me"]"#,
+                r#""index-url":"https://github.com/rust-lang/crates.io-index""#,
+                r#""name":"crates-io""#,
+            ] {
+                assert!(request.contains(part), "{} not in {}", part, request);
+            }
+        }
+
+        #[test]
+        fn not_found_passes_to_the_next_provider() {
+            let dir = scratch("not-found");
+            let missing = script(&dir, "missing", r#"{"Err":{"kind":"not-found"}}"#);
+            let missing_label = missing.args.join(" ");
+            let found = script(&dir, "found", r#"{"Ok":{"kind":"get","token":"s3cret"}}"#);
+            let found_label = found.args.join(" ");
+
+            let mut searched = Vec::new();
+            let token = ask_providers(vec![missing, found], None, &[], &crates_io(), &mut searched)
+                .unwrap()
+                .unwrap();
+            assert_eq!(token.secret, "s3cret");
+            assert_eq!(token.source, format!("credential provider `{}`", found_label));
+            assert_eq!(searched, [missing_label]);
+        }
+
+        #[test]
+        fn errors_carry_their_causes() {
+            let dir = scratch("error");
+            let provider = script(
+                &dir,
+                "broken",
+                r#"{"Err":{"kind":"other","message":"vault is locked","caused-by":["timed out","no agent"]}}"#,
+            );
+
+            let err = run_provider(&provider, None, &crates_io()).unwrap_err().to_string();
+            assert!(err.ends_with("failed: vault is locked: timed out: no agent"), "{}", err);
+        }
+    }
+}
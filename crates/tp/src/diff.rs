@@ -0,0 +1,172 @@
+//! Unified diffs of a file's lines, to show an edit before it is written.
+//! Files `tp` rewrites are small, so a plain longest-common-subsequence
+//! table is fast enough.
+
+use owo_colors::OwoColorize;
+
+/// Lines of context around each change, like `diff -u`.
+const CONTEXT: usize = 3;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Op {
+    Keep,
+    Remove,
+    Add,
+}
+
+/// The unified diff turning `old` into `new`, labelled `a/<path>` and
+/// `b/<path>`; empty when they are the same.
+pub fn unified(path: &str, old: &[String], new: &[String]) -> Vec<String> {
+    let ops = ops(old, new);
+    if ops.iter().all(|(op, _, _)| *op == Op::Keep) {
+        return Vec::new();
+    }
+
+    let mut out = vec![format!("--- a/{}", path), format!("+++ b/{}", path)];
+    let changed: Vec<usize> = (0..ops.len()).filter(|&k| ops[k].0 != Op::Keep).collect();
+    let mut k = 0;
+    while k < changed.len() {
+        // A hunk grows while the next change is close enough for the
+        // contexts to touch or meet
+        let mut last = k;
+        while last + 1 < changed.len() && changed[last + 1] - changed[last] <= 2 * CONTEXT + 1 {
+            last += 1;
+        }
+        let start = changed[k].saturating_sub(CONTEXT);
+        let end = (changed[last] + CONTEXT + 1).min(ops.len());
+        let hunk = &ops[start..end];
+
+        let old_len = hunk.iter().filter(|(op, _, _)| *op != Op::Add).count();
+        let new_len = hunk.iter().filter(|(op, _, _)| *op != Op::Remove).count();
+        let (_, old_start, new_start) = hunk[0];
+        // An empty side is numbered after the line it follows
+        let first = |start: usize, len: usize| if len == 0 { start } else { start + 1 };
+        out.push(format!(
+            "@@ -{},{} +{},{} @@",
+            first(old_start, old_len),
+            old_len,
+            first(new_start, new_len),
+            new_len
+        ));
+        for &(op, i, j) in hunk {
+            out.push(match op {
+                Op::Keep => format!(" {}", old[i]),
+                Op::Remove => format!("-{}", old[i]),
+                Op::Add => format!("+{}", new[j]),
+            });
+        }
+        k = last + 1;
+    }
+    out
+}
+
+/// Prints a diff from [`unified`], colored like `git diff`.
+pub fn print(diff: &[String]) {
+    for line in diff {
+        if line.starts_with("---") || line.starts_with("+++") {
+            say!("{}", line.bold());
+        } else if line.starts_with("@@") {
+            say!("{}", line.cyan());
+        } else if line.starts_with('-') {
+            say!("{}", line.red());
+        } else if line.starts_with('+') {
+            say!("{}", line.green());
+        } else {
+            say!("{}", line);
+        }
+    }
+}
+
+/// The edit script, each step with the index it is at in `old` and `new`.
+fn ops(old: &[String], new: &[String]) -> Vec<(Op, usize, usize)> {
+    // lcs[i][j]: length of the longest common subsequence of old[i..], new[j..]
+    let mut lcs = vec![vec![0usize; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            lcs[i][j] = if old[i] == new[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+
+    let mut ops = Vec::with_capacity(old.len().max(new.len()));
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() || j < new.len() {
+        if i < old.len() && j < new.len() && old[i] == new[j] {
+            ops.push((Op::Keep, i, j));
+            i += 1;
+            j += 1;
+        } else if i < old.len() && (j == new.len() || lcs[i + 1][j] >= lcs[i][j + 1]) {
+            ops.push((Op::Remove, i, j));
+            i += 1;
+        } else {
+            ops.push((Op::Add, i, j));
+            j += 1;
+        }
+    }
+    ops
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(text: &str) -> Vec<String> {
+        text.lines().map(str::to_string).collect()
+    }
+
+    #[test]
+    fn same_lines_make_no_diff() {
+        let old = lines("a\nb\nc");
+        assert!(unified("f.yml", &old, &old).is_empty());
+    }
+
+    #[test]
+    fn replacement_with_context() {
+        let old = lines("1\n2\n3\n4\n5\n6\n7\n8\n9");
+        let new = lines("1\n2\n3\n4\nfive\n6\n7\n8\n9");
+        assert_eq!(
+            unified("f.yml", &old, &new),
+            [
+                "--- a/f.yml",
+                "+++ b/f.yml",
+                "@@ -2,7 +2,7 @@",
+                " 2",
+                " 3",
+                " 4",
+                "-5",
+                "+five",
+                " 6",
+                " 7",
+                " 8"
+            ]
+        );
+    }
+
+    #[test]
+    fn distant_changes_get_their_own_hunks() {
+        let old: Vec<String> = (1..=20).map(|n| n.to_string()).collect();
+        let mut new = old.clone();
+        new.insert(1, "added".to_string());
+        new.remove(18);
+        let headers: Vec<String> = unified("f.yml", &old, &new).into_iter().filter(|l| l.starts_with("@@")).collect();
+        assert_eq!(headers, ["@@ -1,4 +1,5 @@", "@@ -15,6 +16,5 @@"]);
+    }
+
+    #[test]
+    fn changes_whose_contexts_meet_share_a_hunk() {
+        // Six unchanged lines between the changes: three of context each
+        let old = lines("1\n2\n3\n4\n5\n6\n7\n8");
+        let new = lines("one\n2\n3\n4\n5\n6\n7\neight");
+        let headers: Vec<String> = unified("f.yml", &old, &new).into_iter().filter(|l| l.starts_with("@@")).collect();
+        assert_eq!(headers, ["@@ -1,8 +1,8 @@"]);
+    }
+
+    #[test]
+    fn insertion_at_the_start_of_an_empty_file() {
+        let new = lines("on: push");
+        assert_eq!(unified("f.yml", &[], &new)[2], "@@ -0,0 +1,1 @@");
+    }
+}
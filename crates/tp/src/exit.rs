@@ -0,0 +1,87 @@
+//! Exit codes. Scripts and CI gates tell runs apart by these, so they are
+//! part of `tp`'s interface: don't renumber them.
+
+use std::fmt;
+
+use color_eyre::eyre::Error;
+
+/// Any error not covered by a more specific code
+pub const ERROR: u8 = 1;
+/// crates.io rejected the token, or no token could be found
+pub const AUTH_FAILURE: u8 = 5;
+/// crates.io (or the index) couldn't be reached
+pub const NETWORK_FAILURE: u8 = 6;
+
+/// How a run that didn't error out ended.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    /// Everything that needed doing was done (or, in a dry run, would be),
+    /// including when it all already was
+    Success,
+    /// Some crates could not be configured or some configurations not removed
+    PartialFailure,
+    /// There was nothing to act on: no publishable crates, or none the
+    /// token may manage
+    NothingToDo,
+    /// The user answered "no" to a confirmation prompt
+    Aborted,
+    /// `--check` found publishable crates without trusted publishing
+    CheckFailed,
+}
+
+impl Outcome {
+    pub fn code(self) -> u8 {
+        match self {
+            Outcome::Success => 0,
+            Outcome::PartialFailure => 2,
+            Outcome::NothingToDo => 3,
+            Outcome::Aborted => 4,
+            Outcome::CheckFailed => 7,
+        }
+    }
+
+    /// The outcome of a run made of two steps, e.g. `setup --lock`: the
+    /// worse of the two, where acting on something beats having nothing to act on.
+    pub fn and(self, then: Outcome) -> Outcome {
+        match (self, then) {
+            (Outcome::PartialFailure, _) | (_, Outcome::PartialFailure) => Outcome::PartialFailure,
+            (Outcome::Aborted, _) | (_, Outcome::Aborted) => Outcome::Aborted,
+            (Outcome::NothingToDo, then) => then,
+            (outcome, _) => outcome,
+        }
+    }
+
+    /// Stable name, as used in JSON output
+    pub fn id(self) -> &'static str {
+        match self {
+            Outcome::Success => "success",
+            Outcome::PartialFailure => "partial-failure",
+            Outcome::NothingToDo => "nothing-to-do",
+            Outcome::Aborted => "aborted",
+            Outcome::CheckFailed => "check-failed",
+        }
+    }
+}
+
+/// Marks an error as an authentication problem so it gets its own exit code.
+#[derive(Debug)]
+pub struct AuthError(pub String);
+
+impl fmt::Display for AuthError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for AuthError {}
+
+/// Exit code and stable name for a run that failed with `err`.
+pub fn classify(err: &Error) -> (u8, &'static str) {
+    if err.chain().any(|e| e.is::<AuthError>()) {
+        (AUTH_FAILURE, "auth-failure")
+    } else if err.chain().any(|e| e.is::<reqwest::Error>()) {
+        (NETWORK_FAILURE, "network-failure")
+    } else {
+        (ERROR, "error")
+    }
+}
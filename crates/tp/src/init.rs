@@ -0,0 +1,287 @@
+//! `tp workflow init`: writes a release workflow that publishes with trusted
+//! publishing from the start, for repositories that don't have one yet.
+//! Every template grants its publishing job `id-token: write` and trades the
+//! job's OIDC token for a short-lived crates.io token with
+//! `rust-lang/crates-io-auth-action`, so no secret is ever needed.
+
+use color_eyre::eyre::{Result, bail};
+use owo_colors::OwoColorize;
+
+use crate::exit::Outcome;
+use crate::overrides::Overrides;
+use crate::prompt::Prompt;
+use crate::registry::Registry;
+use crate::report::Report;
+use crate::workflow::workflows_dir;
+use crate::{TargetArgs, default_branch, setup};
+
+pub struct Template {
+    /// What `--template` takes
+    pub id: &'static str,
+    /// Filename written when `-w` isn't given
+    pub file: &'static str,
+    pub description: &'static str,
+    body: &'static str,
+}
+
+/// Stand-ins in the template bodies. `${{ }}` belongs to GitHub Actions, so
+/// the placeholders use something that can't clash with it.
+const ENVIRONMENT: &str = "    __ENVIRONMENT__\n";
+const BRANCH: &str = "__BRANCH__";
+
+pub const TEMPLATES: &[Template] = &[
+    Template {
+        id: "release-plz",
+        file: "release-plz.yml",
+        description: "release-plz: opens release PRs, publishes what they bump once merged",
+        body: RELEASE_PLZ,
+    },
+    Template {
+        id: "tag",
+        file: "release.yml",
+        description: "cargo publish --workspace when a v* tag is pushed",
+        body: TAG,
+    },
+    Template {
+        id: "manual",
+        file: "publish.yml",
+        description: "cargo publish --workspace when run by hand from the Actions tab",
+        body: MANUAL,
+    },
+];
+
+const RELEASE_PLZ: &str = r#"name: Release-plz
+
+on:
+  push:
+    branches:
+      - __BRANCH__
+
+jobs:
+  # Publishes the crates whose version was bumped, and tags them
+  release-plz-release:
+    name: Release-plz release
+    runs-on: ubuntu-latest
+    permissions:
+      contents: write
+      id-token: write
+    __ENVIRONMENT__
+    steps:
+      - uses: actions/checkout@v5
+        with:
+          fetch-depth: 0
+          persist-credentials: false
+      - uses: dtolnay/rust-toolchain@stable
+      - uses: rust-lang/crates-io-auth-action@v1
+        id: auth
+      - uses: release-plz/action@v0.5
+        with:
+          command: release
+        env:
+          GITHUB_TOKEN: ${{ secrets.GITHUB_TOKEN }}
+          CARGO_REGISTRY_TOKEN: ${{ steps.auth.outputs.token }}
+
+  # Opens (or updates) the PR bumping versions and changelogs
+  release-plz-pr:
+    name: Release-plz PR
+    runs-on: ubuntu-latest
+    permissions:
+      contents: write
+      pull-requests: write
+    concurrency:
+      group: release-plz-${{ github.ref }}
+      cancel-in-progress: false
+    steps:
+      - uses: actions/checkout@v5
+        with:
+          fetch-depth: 0
+          persist-credentials: false
+      - uses: dtolnay/rust-toolchain@stable
+      - uses: release-plz/action@v0.5
+        with:
+          command: release-pr
+        env:
+          GITHUB_TOKEN: ${{ secrets.GITHUB_TOKEN }}
+"#;
+
+const TAG: &str = r#"name: Release
+
+on:
+  push:
+    tags:
+      - "v*"
+
+jobs:
+  publish:
+    name: Publish to crates.io
+    runs-on: ubuntu-latest
+    permissions:
+      contents: read
+      id-token: write
+    __ENVIRONMENT__
+    steps:
+      - uses: actions/checkout@v5
+        with:
+          persist-credentials: false
+      - uses: dtolnay/rust-toolchain@stable
+      - uses: rust-lang/crates-io-auth-action@v1
+        id: auth
+      - run: cargo publish --workspace
+        env:
+          CARGO_REGISTRY_TOKEN: ${{ steps.auth.outputs.token }}
+"#;
+
+const MANUAL: &str = r#"name: Publish
+
+on:
+  workflow_dispatch:
+
+jobs:
+  publish:
+    name: Publish to crates.io
+    runs-on: ubuntu-latest
+    permissions:
+      contents: read
+      id-token: write
+    __ENVIRONMENT__
+    steps:
+      - uses: actions/checkout@v5
+        with:
+          persist-credentials: false
+      - uses: dtolnay/rust-toolchain@stable
+      - uses: rust-lang/crates-io-auth-action@v1
+        id: auth
+      - run: cargo publish --workspace
+        env:
+          CARGO_REGISTRY_TOKEN: ${{ steps.auth.outputs.token }}
+"#;
+
+impl Template {
+    pub fn find(id: &str) -> Result<&'static Template> {
+        match TEMPLATES.iter().find(|t| t.id == id) {
+            Some(template) => Ok(template),
+            None => {
+                let ids: Vec<&str> = TEMPLATES.iter().map(|t| t.id).collect();
+                bail!("Unknown template `{}`; expected one of: {}", id, ids.join(", "))
+            }
+        }
+    }
+
+    /// The workflow, with the publishing job running in `environment` if
+    /// given and release-plz watching `branch`.
+    pub fn render(&self, environment: Option<&str>, branch: &str) -> String {
+        let environment = match environment {
+            Some(env) => format!("    environment: {}\n", env),
+            None => String::new(),
+        };
+        self.body.replace(ENVIRONMENT, &environment).replace(BRANCH, branch)
+    }
+}
+
+/// `tp workflow init`: writes the workflow, then offers to configure
+/// trusted publishing for it right away, as `tp setup -w <file>` would.
+/// `config` stands in for the flags the command line leaves out.
+#[allow(clippy::too_many_arguments)]
+pub async fn init(
+    template: Option<&str>,
+    target: &mut TargetArgs,
+    config: &Overrides,
+    registry: &Registry,
+    prompt: &Prompt,
+    report: &mut Report,
+    force: bool,
+    dry_run: bool,
+) -> Result<Outcome> {
+    report.dry_run = dry_run;
+
+    if target.gitlab {
+        bail!("`tp workflow init` only writes GitHub workflows; GitLab CI isn't supported");
+    }
+
+    let file = write(template, target, config, prompt, report, force, dry_run)?;
+    if dry_run {
+        say!(
+            "{}",
+            format!("(dry run) Would then offer to configure trusted publishing for {}", file).dimmed()
+        );
+        return Ok(Outcome::Success);
+    }
+
+    // A configuration can name a workflow before it is pushed, so there is
+    // no need to wait for a commit
+    say!();
+    let later = format!("Run `tp setup -w {}` to configure trusted publishing for it.", file);
+    if !prompt.can_confirm() {
+        say!("{}", later.dimmed());
+        return Ok(Outcome::Success);
+    }
+    if !prompt.confirm("Configure trusted publishing for it now?")? {
+        say!("{}", later.dimmed());
+        return Ok(Outcome::Success);
+    }
+    say!();
+
+    target.workflow = Some(file);
+    setup(target, config, registry, prompt, report, false, false, false).await
+}
+
+/// Writes the chosen template to `.github/workflows/`, refusing to replace an
+/// existing file unless `force`. `-w` names the file and `--environment`
+/// the environment of the publishing job. Returns the filename written, which
+/// is what the trusted publishing configuration has to name. With `dry_run`
+/// the workflow is only printed.
+fn write(
+    template: Option<&str>,
+    target: &TargetArgs,
+    config: &Overrides,
+    prompt: &Prompt,
+    report: &mut Report,
+    force: bool,
+    dry_run: bool,
+) -> Result<String> {
+    let chosen = target.over(config);
+    let environment = chosen.environment.as_deref();
+    let template = match template {
+        Some(id) => Template::find(id)?,
+        None => {
+            let items: Vec<String> = TEMPLATES
+                .iter()
+                .map(|t| format!("{}  {}", t.id, format!("({})", t.description).dimmed()))
+                .collect();
+            &TEMPLATES[prompt.select("Select a template", &items, "--template <name>")?]
+        }
+    };
+
+    // crates.io matches the bare filename, so the workflow has to live
+    // directly in .github/workflows
+    let file = match chosen.workflow.as_deref() {
+        Some(file) if file.contains('/') => {
+            bail!("`{}` is a path; give just the filename, it is written to .github/workflows/", file)
+        }
+        Some(file) if file.ends_with(".yml") || file.ends_with(".yaml") => file.to_string(),
+        Some(file) => format!("{}.yml", file),
+        None => template.file.to_string(),
+    };
+    let path = workflows_dir().join(&file);
+    report.workflow = Some(file.clone());
+    report.environment = environment.map(str::to_string);
+
+    let contents = template.render(environment, &default_branch());
+
+    // Checked before the dry run returns, so it tells what the real run would do
+    if path.exists() && !force {
+        bail!("{} already exists; pass --force to overwrite it", path.display());
+    }
+
+    if dry_run {
+        say!("{} {}\n", "(dry run) Would write".dimmed(), path.display().yellow());
+        say!("{}", contents);
+        return Ok(file);
+    }
+
+    std::fs::create_dir_all(workflows_dir())?;
+    std::fs::write(&path, &contents)?;
+    say!("{} Wrote {} ({})", "✅".green(), path.display().yellow(), template.id);
+
+    Ok(file)
+}
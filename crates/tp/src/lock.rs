@@ -0,0 +1,246 @@
+//! `tp lock` and `setup --lock`: turn on crates.io's "trusted publishing
+//! only" setting, after which the crate refuses publishes with an API token,
+//! so a leaked token can no longer push a release. Only crates with a trusted
+//! publishing configuration are locked; locking one without would leave no
+//! way to publish it at all.
+
+use std::collections::HashSet;
+
+use color_eyre::eyre::{Result, WrapErr, bail};
+use futures::{StreamExt, stream};
+use owo_colors::OwoColorize;
+
+use crate::api::{ApiClient, list_trustpub_configs, set_trustpub_only, trustpub_only};
+use crate::exit::Outcome;
+use crate::owners;
+use crate::prompt::Prompt;
+use crate::registry::Registry;
+use crate::report::{PackageReport, Report};
+use crate::select::PackageArgs;
+use crate::{Forge, Package, check_published, load_workspace, progress_bar, resolve_token};
+
+pub async fn lock(
+    packages: &PackageArgs,
+    token_env: Option<&str>,
+    registry: &Registry,
+    prompt: &Prompt,
+    report: &mut Report,
+    dry_run: bool,
+) -> Result<Outcome> {
+    report.dry_run = dry_run;
+
+    let token = resolve_token(token_env, registry, report)?;
+
+    let packages = load_workspace(registry, packages, report)?.packages;
+    report.packages.extend(packages.iter().map(PackageReport::new));
+    if packages.is_empty() {
+        say!("{}", "No publishable crates found.".yellow());
+        return Ok(Outcome::NothingToDo);
+    }
+
+    let client = ApiClient::new(registry).await?;
+
+    // A crate that was never published has no settings, nor configurations
+    let published = check_published(&client, &packages).await?;
+    let (published, unpublished): (Vec<Package>, Vec<Package>) = packages
+        .into_iter()
+        .partition(|pkg| published.get(&pkg.name) == Some(&true));
+    for pkg in &published {
+        if let Some(p) = report.package_mut(&pkg.name) {
+            p.published = Some(true);
+        }
+    }
+    for pkg in &unpublished {
+        if let Some(p) = report.package_mut(&pkg.name) {
+            p.published = Some(false);
+            p.result = Some("skipped".to_string());
+        }
+    }
+    if !unpublished.is_empty() {
+        let names: Vec<&str> = unpublished.iter().map(|pkg| pkg.name.as_str()).collect();
+        say!("{} {}", "⏭️  Not published yet:".dimmed(), names.join(", ").dimmed());
+    }
+    if published.is_empty() {
+        return Ok(Outcome::NothingToDo);
+    }
+
+    lock_crates(&client, &token, &published, prompt, report, dry_run).await
+}
+
+/// `setup --lock`: locks the crates of the setup run that the report says
+/// are on crates.io; in a dry run, skeletons are only pretend-published.
+pub async fn lock_published(
+    client: &ApiClient,
+    token: &str,
+    packages: &[Package],
+    prompt: &Prompt,
+    report: &mut Report,
+    dry_run: bool,
+) -> Result<Outcome> {
+    let published: Vec<Package> = packages
+        .iter()
+        .filter(|pkg| {
+            report
+                .packages
+                .iter()
+                .any(|p| p.name == pkg.name && p.published == Some(true))
+        })
+        .cloned()
+        .collect();
+    if published.is_empty() {
+        return Ok(Outcome::NothingToDo);
+    }
+    lock_crates(client, token, &published, prompt, report, dry_run).await
+}
+
+/// Locks every crate of `packages` (all published) that has a trusted
+/// publishing configuration. Results are only recorded for crates the run
+/// hasn't given one yet, so `setup --lock` keeps saying what setup did.
+pub async fn lock_crates(
+    client: &ApiClient,
+    token: &str,
+    packages: &[Package],
+    prompt: &Prompt,
+    report: &mut Report,
+    dry_run: bool,
+) -> Result<Outcome> {
+    say!("\n{}", "🔍 Checking existing configurations...".cyan());
+    let listing = list_trustpub_configs(client, token, packages, &Forge::ALL).await?;
+    owners::print_refused(&listing.not_owned, client.label(), report);
+    let configured: HashSet<&str> = listing.configs.iter().map(|cfg| cfg.crate_name.as_str()).collect();
+    let (configured, unconfigured): (Vec<&Package>, Vec<&Package>) = packages
+        .iter()
+        .filter(|pkg| !listing.not_owned.contains(&pkg.name))
+        .partition(|pkg| configured.contains(pkg.name.as_str()));
+
+    let mut locked = Vec::new();
+    let mut to_lock = Vec::new();
+    for (pkg, state) in lookup(client, &configured).await {
+        let state = state.wrap_err_with(|| format!("Could not check whether {} is locked", pkg.name))?;
+        let Some(state) = state else {
+            bail!("This registry has no \"trusted publishing only\" setting to turn on");
+        };
+        if let Some(p) = report.package_mut(&pkg.name) {
+            p.trustpub_only = Some(state);
+        }
+        if state {
+            locked.push(pkg);
+        } else {
+            to_lock.push(pkg);
+        }
+    }
+    locked.sort_by(|a, b| a.name.cmp(&b.name));
+    to_lock.sort_by(|a, b| a.name.cmp(&b.name));
+
+    let set_result = |report: &mut Report, name: &str, result: &str| {
+        if let Some(p) = report.package_mut(name).filter(|p| p.result.is_none()) {
+            p.result = Some(result.to_string());
+        }
+    };
+
+    say!("\n{}", "🔒 Trusted publishing only:".cyan());
+    if !locked.is_empty() {
+        let names: Vec<&str> = locked.iter().map(|pkg| pkg.name.as_str()).collect();
+        say!("   {} {} {}", "✓".green(), "already on:".dimmed(), names.join(", "));
+        for pkg in &locked {
+            set_result(report, &pkg.name, "already-locked");
+        }
+    }
+    for pkg in &to_lock {
+        say!("   {} {}", "•".dimmed(), pkg.name.bright_white());
+    }
+    if !unconfigured.is_empty() {
+        let names: Vec<&str> = unconfigured.iter().map(|pkg| pkg.name.as_str()).collect();
+        say!("   {} {} {}", "✗".red(), "refused:".dimmed(), names.join(", ").red());
+        say!(
+            "     {}",
+            "(no trusted publishing configuration yet; locking them would leave no way to publish them)".dimmed()
+        );
+        for pkg in &unconfigured {
+            set_result(report, &pkg.name, "not-configured");
+        }
+    }
+
+    if to_lock.is_empty() {
+        say!("\n{} Nothing to lock.", "✓".green());
+        return Ok(Outcome::Success);
+    }
+
+    if dry_run {
+        for pkg in &to_lock {
+            set_result(report, &pkg.name, "would-lock");
+        }
+        say!(
+            "\n{} Would turn on trusted publishing only for {} crate{}.",
+            "(dry run)".dimmed(),
+            to_lock.len().to_string().bright_white(),
+            if to_lock.len() == 1 { "" } else { "s" }
+        );
+        return Ok(Outcome::Success);
+    }
+
+    say!();
+    if !prompt.confirm("Refuse token-based publishes for these crates from now on?")? {
+        say!("{}", "Aborted.".yellow());
+        return Ok(Outcome::Aborted);
+    }
+
+    let pb = progress_bar(to_lock.len() as u64);
+    let mut errors = Vec::new();
+    for pkg in &to_lock {
+        pb.set_message(format!("Locking {}", pkg.name));
+        match set_trustpub_only(client, token, &pkg.name, true).await {
+            Ok(()) => {
+                set_result(report, &pkg.name, "locked");
+                if let Some(p) = report.package_mut(&pkg.name) {
+                    p.trustpub_only = Some(true);
+                }
+            }
+            Err(e) => {
+                report.add_error(&pkg.name, &e.to_string());
+                errors.push((pkg.name.as_str(), e.to_string()));
+            }
+        }
+        pb.inc(1);
+    }
+    pb.finish_and_clear();
+
+    if !errors.is_empty() {
+        say!("\n{}", "❌ Errors turning on trusted publishing only:".red());
+        for (name, err) in &errors {
+            say!("   {} {} {}", name.cyan(), "✗".red(), err.dimmed());
+        }
+    }
+    say!(
+        "\n{} Locked {}/{} crate{}: they can now only be published through trusted publishing.",
+        if errors.is_empty() { "✅".green().to_string() } else { "⚠️".yellow().to_string() },
+        (to_lock.len() - errors.len()).to_string().bright_white().bold(),
+        to_lock.len(),
+        if to_lock.len() == 1 { "" } else { "s" }
+    );
+
+    if errors.is_empty() {
+        Ok(Outcome::Success)
+    } else {
+        Ok(Outcome::PartialFailure)
+    }
+}
+
+/// The "trusted publishing only" setting of each crate, looked up in parallel.
+pub async fn lookup<'a>(client: &ApiClient, crates: &[&'a Package]) -> Vec<(&'a Package, Result<Option<bool>>)> {
+    let pb = progress_bar(crates.len() as u64);
+    pb.set_message("Checking settings");
+    let states = stream::iter(crates.iter().map(|&pkg| {
+        let pb = &pb;
+        async move {
+            let state = trustpub_only(client, &pkg.name).await;
+            pb.inc(1);
+            (pkg, state)
+        }
+    }))
+    .buffer_unordered(20)
+    .collect()
+    .await;
+    pb.finish_and_clear();
+    states
+}
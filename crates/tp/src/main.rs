@@ -1,50 +1,374 @@
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 use std::io::{Write, stdout};
 use std::path::PathBuf;
-use std::process::Command;
+use std::process::{Command, ExitCode};
 use std::time::Duration;
 
-use color_eyre::eyre::{bail, eyre, Result};
-use dialoguer::{Confirm, Select, theme::ColorfulTheme};
+use color_eyre::eyre::{bail, Result, WrapErr};
 use facet::Facet;
 use figue::{self as args, FigueBuiltins};
-use facet_json::{from_str, to_string};
+use facet_json::from_str;
 use futures::{StreamExt, stream};
-use indicatif::{ProgressBar, ProgressStyle};
+use indicatif::{ProgressBar, ProgressDrawTarget, ProgressStyle};
 use owo_colors::OwoColorize;
-use reqwest::Client;
 
-const BASE_URL: &str = "https://crates.io";
-const USER_AGENT: &str = "tp-trusted-publishing-setup (contact: amos@bearcove.eu)";
+use crate::api::{
+    ApiClient, NotOwned, TrustpubConfig, check_token, crate_exists, create_trustpub_config, current_user, delete_trustpub_config,
+    list_trustpub_configs,
+};
+use crate::config::Config;
+use crate::exit::{AuthError, Outcome};
+use crate::overrides::{Metadata, Overrides};
+use crate::prompt::{Prompt, PromptArgs};
+use crate::registry::{CRATES_IO, Registry, RegistryArgs};
+use crate::report::{CheckReport, OutputArgs, PackageReport, Report};
+use crate::select::PackageArgs;
+
+/// `println!` for human-readable output. With `--format json` stdout is
+/// reserved for the report, so the text goes to stderr instead.
+macro_rules! say {
+    ($($arg:tt)*) => {
+        if $crate::report::is_json() {
+            eprintln!($($arg)*)
+        } else {
+            println!($($arg)*)
+        }
+    };
+}
+
+mod api;
+mod config;
+mod credentials;
+mod diff;
+mod exit;
+mod init;
+mod lock;
+mod migrate;
+mod overrides;
+mod owners;
+mod prompt;
+mod registry;
+mod remove;
+mod report;
+mod select;
+mod status;
+mod sync;
+mod workflow;
 
 #[derive(Facet, Debug)]
 struct Args {
-    /// GitHub repository owner (e.g., "facet-rs"). Detected from git remote if not provided.
+    /// Command to run (default: setup)
+    #[facet(args::subcommand)]
+    command: Option<Subcommand>,
+
+    /// Standard CLI options (--help, --version, --completions)
+    #[facet(flatten)]
+    builtins: FigueBuiltins,
+}
+
+#[derive(Facet, Debug)]
+#[repr(u8)]
+enum Subcommand {
+    /// Configure trusted publishing for all publishable crates in the workspace
+    Setup {
+        #[facet(flatten)]
+        target: TargetArgs,
+
+        #[facet(flatten)]
+        registry: RegistryArgs,
+
+        #[facet(flatten)]
+        prompt: PromptArgs,
+
+        #[facet(flatten)]
+        output: OutputArgs,
+
+        /// Dry run - don't actually configure trusted publishing
+        #[facet(args::named, args::short = 'n', default)]
+        dry_run: bool,
+
+        /// Then turn on "trusted publishing only" for every crate with a configuration
+        #[facet(args::named, default)]
+        lock: bool,
+    },
+
+    /// Reconcile configurations with the desired repo/workflow, reporting drift
+    Sync {
+        #[facet(flatten)]
+        target: TargetArgs,
+
+        #[facet(flatten)]
+        registry: RegistryArgs,
+
+        #[facet(flatten)]
+        prompt: PromptArgs,
+
+        #[facet(flatten)]
+        output: OutputArgs,
+
+        /// Dry run - don't actually change anything
+        #[facet(args::named, args::short = 'n', default)]
+        dry_run: bool,
+
+        /// Delete stray configurations that point at a different repository or workflow
+        #[facet(args::named, default)]
+        prune: bool,
+
+        /// Then turn on "trusted publishing only" for every crate with a configuration
+        #[facet(args::named, default)]
+        lock: bool,
+    },
+
+    /// Show the trusted publishing state of each crate without changing anything
+    Status {
+        #[facet(flatten)]
+        target: TargetArgs,
+
+        #[facet(flatten)]
+        registry: RegistryArgs,
+
+        /// Exit with a non-zero code if any publishable crate lacks trusted publishing (for CI)
+        #[facet(args::named, default)]
+        check: bool,
+
+        #[facet(flatten)]
+        output: OutputArgs,
+    },
+
+    /// Remove trusted publishing configurations matching the given filters
+    Remove {
+        /// Only remove configurations for this repository owner
+        #[facet(args::positional)]
+        owner: Option<String>,
+
+        /// Only remove configurations for this repository name
+        #[facet(args::positional)]
+        repo: Option<String>,
+
+        /// Only remove configurations for this workflow filename
+        #[facet(args::named, args::short = 'w')]
+        workflow: Option<String>,
+
+        /// Environment variable to read the token from (default: wherever cargo would find it)
+        #[facet(args::named, args::short = 'e')]
+        token_env: Option<String>,
+
+        #[facet(flatten)]
+        packages: PackageArgs,
+
+        /// Remove every configuration of every workspace crate (required when no filter is given)
+        #[facet(args::named, default)]
+        all: bool,
+
+        /// Dry run - only show which configurations would be removed
+        #[facet(args::named, args::short = 'n', default)]
+        dry_run: bool,
+
+        #[facet(flatten)]
+        registry: RegistryArgs,
+
+        #[facet(flatten)]
+        prompt: PromptArgs,
+
+        #[facet(flatten)]
+        output: OutputArgs,
+    },
+
+    /// Turn on "trusted publishing only" for every crate with a configuration, refusing token publishes
+    Lock {
+        /// Environment variable to read the token from (default: wherever cargo would find it)
+        #[facet(args::named, args::short = 'e')]
+        token_env: Option<String>,
+
+        #[facet(flatten)]
+        packages: PackageArgs,
+
+        /// Dry run - only show which crates would be locked
+        #[facet(args::named, args::short = 'n', default)]
+        dry_run: bool,
+
+        #[facet(flatten)]
+        registry: RegistryArgs,
+
+        #[facet(flatten)]
+        prompt: PromptArgs,
+
+        #[facet(flatten)]
+        output: OutputArgs,
+    },
+
+    /// Show each crate's owners and bring them in line with a desired list
+    Owners {
+        /// Owner every crate should have: a crates.io login, or a team like "github:org:team"; can be repeated
+        #[facet(args::named, default)]
+        crate_owner: Vec<String>,
+
+        /// Also remove owners that aren't given with --crate-owner
+        #[facet(args::named, default)]
+        remove_extra: bool,
+
+        /// Environment variable to read the token from (default: wherever cargo would find it)
+        #[facet(args::named, args::short = 'e')]
+        token_env: Option<String>,
+
+        #[facet(flatten)]
+        packages: PackageArgs,
+
+        /// Dry run - only show which owners would be added or removed
+        #[facet(args::named, args::short = 'n', default)]
+        dry_run: bool,
+
+        #[facet(flatten)]
+        registry: RegistryArgs,
+
+        #[facet(flatten)]
+        prompt: PromptArgs,
+
+        #[facet(flatten)]
+        output: OutputArgs,
+    },
+
+    /// Write the GitHub workflow that publishes the crates, or move an existing one to trusted publishing
+    Workflow {
+        #[facet(args::subcommand)]
+        command: WorkflowCommand,
+    },
+}
+
+// Parsed once per run, so the size of the largest variant doesn't matter
+#[allow(clippy::large_enum_variant)]
+#[derive(Facet, Debug)]
+#[repr(u8)]
+enum WorkflowCommand {
+    /// Write a release workflow set up for trusted publishing, then offer to configure crates.io for it
+    Init {
+        /// Template to start from: "release-plz", "tag" or "manual". Asked for if not provided.
+        #[facet(args::named)]
+        template: Option<String>,
+
+        /// Overwrite the workflow file if it already exists
+        #[facet(args::named, default)]
+        force: bool,
+
+        #[facet(flatten)]
+        target: TargetArgs,
+
+        #[facet(flatten)]
+        registry: RegistryArgs,
+
+        #[facet(flatten)]
+        prompt: PromptArgs,
+
+        #[facet(flatten)]
+        output: OutputArgs,
+
+        /// Dry run - print the workflow instead of writing it
+        #[facet(args::named, args::short = 'n', default)]
+        dry_run: bool,
+    },
+
+    /// Move a workflow publishing with secrets.CARGO_REGISTRY_TOKEN to trusted publishing, in place
+    Migrate {
+        /// Workflow filename (e.g., "release.yml"). Auto-detected if not provided.
+        #[facet(args::named, args::short = 'w')]
+        workflow: Option<String>,
+
+        #[facet(flatten)]
+        prompt: PromptArgs,
+
+        #[facet(flatten)]
+        output: OutputArgs,
+
+        /// Dry run - only show the diff
+        #[facet(args::named, args::short = 'n', default)]
+        dry_run: bool,
+    },
+}
+
+/// Options shared by every command: which repository/workflow and crates to
+/// target and where the crates.io token comes from.
+#[derive(Facet, Debug, Default)]
+struct TargetArgs {
+    /// GitHub repository owner or GitLab namespace (e.g., "facet-rs"). Detected from git remote if not provided.
     #[facet(args::positional)]
     owner: Option<String>,
 
-    /// GitHub repository name (e.g., "facet"). Detected from git remote if not provided.
+    /// GitHub repository or GitLab project name (e.g., "facet"). Detected from git remote if not provided.
     #[facet(args::positional)]
     repo: Option<String>,
 
-    /// Workflow filename (e.g., "release-plz.yml"). Auto-detected from .github/workflows/ if not provided.
+    /// Workflow filename (e.g., "release-plz.yml"), or the CI file path for GitLab. Auto-detected if not provided.
     #[facet(args::named, args::short = 'w')]
     workflow: Option<String>,
 
-    /// Environment variable to override the crates.io token (default: read from ~/.cargo/credentials.toml)
+    /// Deployment environment the publishing job must run in (e.g., "release").
+    /// Suggested from the workflow's publishing job if not provided.
+    #[facet(args::named)]
+    environment: Option<String>,
+
+    /// Target a GitLab project instead of a GitHub repository (implied by a gitlab.com remote)
+    #[facet(args::named, default)]
+    gitlab: bool,
+
+    /// Environment variable to read the token from (default: wherever cargo would find it)
     #[facet(args::named, args::short = 'e')]
     token_env: Option<String>,
 
-    /// Dry run - don't actually configure trusted publishing
-    #[facet(args::named, args::short = 'n', default)]
-    dry_run: bool,
-
-    /// Standard CLI options (--help, --version, --completions)
     #[facet(flatten)]
-    builtins: FigueBuiltins,
+    packages: PackageArgs,
+}
+
+impl TargetArgs {
+    /// The workflow and environment given on the command line, with what
+    /// they leave unset taken from `lower`. As between any two layers, `-w`
+    /// without `--environment` drops `lower`'s environment, which belongs to
+    /// `lower`'s workflow.
+    fn over(&self, lower: &Overrides) -> Overrides {
+        let flags = Overrides {
+            workflow: self.workflow.clone(),
+            environment: self.environment.clone(),
+            ..Overrides::default()
+        };
+        flags.or(lower)
+    }
+}
+
+/// Where the repository lives, which decides the kind of trusted publishing
+/// configuration crates.io gets.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Forge {
+    Github,
+    Gitlab,
+}
+
+impl Forge {
+    const ALL: [Forge; 2] = [Forge::Github, Forge::Gitlab];
+
+    fn host(self) -> &'static str {
+        match self {
+            Forge::Github => "github.com",
+            Forge::Gitlab => "gitlab.com",
+        }
+    }
+
+    /// Stable lowercase name, as used in JSON output
+    fn id(self) -> &'static str {
+        match self {
+            Forge::Github => "github",
+            Forge::Gitlab => "gitlab",
+        }
+    }
+
+    /// Path segment of the crates.io trusted publishing endpoints
+    fn endpoint(self) -> &'static str {
+        match self {
+            Forge::Github => "github_configs",
+            Forge::Gitlab => "gitlab_configs",
+        }
+    }
 }
 
-fn detect_github_repo() -> Result<(String, String)> {
+fn detect_repo() -> Result<(Forge, String, String)> {
     let output = Command::new("git")
         .args(["remote", "get-url", "origin"])
         .output()?;
@@ -55,50 +379,42 @@ fn detect_github_repo() -> Result<(String, String)> {
 
     let url = String::from_utf8(output.stdout)?.trim().to_string();
 
-    // Parse SSH format: git@github.com:owner/repo.git
-    if let Some(rest) = url.strip_prefix("git@github.com:") {
+    for forge in [Forge::Github, Forge::Gitlab] {
+        let host = forge.host();
+
+        // Parse SSH formats: git@github.com:owner/repo.git, ssh://git@github.com/owner/repo.git
+        // and HTTPS format: https://github.com/owner/repo.git
+        let Some(rest) = url
+            .strip_prefix(format!("git@{}:", host).as_str())
+            .or_else(|| url.strip_prefix(format!("ssh://git@{}/", host).as_str()))
+            .or_else(|| url.strip_prefix(format!("https://{}/", host).as_str()))
+            .or_else(|| url.strip_prefix(format!("http://{}/", host).as_str()))
+        else {
+            continue;
+        };
+        let rest = rest.trim_end_matches('/');
         let rest = rest.strip_suffix(".git").unwrap_or(rest);
-        if let Some((owner, repo)) = rest.split_once('/') {
-            return Ok((owner.to_string(), repo.to_string()));
-        }
-    }
 
-    // Parse HTTPS format: https://github.com/owner/repo.git
-    if let Some(rest) = url
-        .strip_prefix("https://github.com/")
-        .or_else(|| url.strip_prefix("http://github.com/"))
-    {
-        let rest = rest.strip_suffix(".git").unwrap_or(rest);
-        if let Some((owner, repo)) = rest.split_once('/') {
-            return Ok((owner.to_string(), repo.to_string()));
+        // GitLab projects can live in nested groups: the namespace is
+        // everything up to the last path segment.
+        let split = match forge {
+            Forge::Github => rest.split_once('/'),
+            Forge::Gitlab => rest.rsplit_once('/'),
+        };
+        if let Some((owner, repo)) = split {
+            return Ok((forge, owner.to_string(), repo.to_string()));
         }
     }
 
     bail!(
-        "Could not parse GitHub owner/repo from remote URL: {}\nSpecify owner and repo explicitly.",
+        "Could not parse GitHub or GitLab owner/repo from remote URL: {}\nSpecify owner and repo explicitly.",
         url
     );
 }
 
-#[derive(Facet, Debug)]
-struct CargoCredentials {
-    registry: Option<RegistryCredentials>,
-}
-
-#[derive(Facet, Debug)]
-struct RegistryCredentials {
-    token: Option<String>,
-}
-
-fn get_cargo_credentials_path() -> PathBuf {
-    dirs::home_dir()
-        .unwrap_or_else(|| PathBuf::from("."))
-        .join(".cargo")
-        .join("credentials.toml")
-}
-
-fn detect_workflow_files() -> Result<Vec<String>> {
-    let workflows_dir = PathBuf::from(".github/workflows");
+/// Every workflow file in `.github/workflows`.
+fn list_workflow_files() -> Result<Vec<String>> {
+    let workflows_dir = workflow::workflows_dir();
     if !workflows_dir.exists() {
         return Ok(Vec::new());
     }
@@ -119,7 +435,104 @@ fn detect_workflow_files() -> Result<Vec<String>> {
     Ok(files)
 }
 
-fn select_workflow(files: &[String]) -> Result<String> {
+/// The workflow files a configuration could name. A reusable workflow that
+/// only ever runs when called is left out in favour of its callers in the
+/// repository, which are what crates.io sees.
+fn detect_workflow_files() -> Result<Vec<String>> {
+    let files = list_workflow_files()?;
+    let mut candidates = Vec::with_capacity(files.len());
+    for file in &files {
+        let only_called = workflow::Workflow::load(file).is_ok_and(|w| w.only_called());
+        let callers = if only_called { workflow::callers(file, &files) } else { Vec::new() };
+        if callers.is_empty() {
+            candidates.push(file.clone());
+        } else {
+            say!(
+                "{}",
+                format!("↪️  {} is a reusable workflow, called by {}", file, callers.join(", ")).dimmed()
+            );
+        }
+    }
+    Ok(candidates)
+}
+
+/// crates.io checks the workflow that started the run, so a configuration
+/// naming a reusable workflow never matches. Warns when `name` only ever
+/// runs when called, and offers the workflows calling it instead.
+fn resolve_reusable(name: &str, prompt: &Prompt) -> Result<String> {
+    if !workflow::Workflow::load(name).is_ok_and(|w| w.only_called()) {
+        return Ok(name.to_string());
+    }
+
+    say!(
+        "{} {}",
+        "⚠️ ".yellow(),
+        format!(
+            "{} only runs when another workflow calls it (on: workflow_call). crates.io checks the calling workflow, so a configuration naming {} would never match.",
+            name, name
+        )
+        .yellow()
+    );
+    let callers = workflow::callers(name, &list_workflow_files()?);
+    if callers.is_empty() {
+        say!(
+            "   {}",
+            "No workflow in .github/workflows calls it; pass -w with the one that does.".dimmed()
+        );
+        return Ok(name.to_string());
+    }
+    say!("   {} {}", "Called by:".dimmed(), callers.join(", "));
+    if !prompt.can_select() {
+        say!("   {}", "Pass -w with one of them to configure it instead.".dimmed());
+        return Ok(name.to_string());
+    }
+
+    let mut items = callers.clone();
+    items.push(format!("{}  {}", name, "(keep it anyway)".dimmed()));
+    let selection = prompt.select("Configure which workflow?", &items, "-w <file>")?;
+    Ok(callers.get(selection).cloned().unwrap_or_else(|| name.to_string()))
+}
+
+/// [`resolve_reusable`] for the workflows crates name in
+/// `[package.metadata.tp]`: each reusable one mapped to the caller to
+/// configure instead. `global` is the run's workflow as picked and as
+/// resolved, so it isn't asked about twice.
+fn resolve_crate_workflows(
+    packages: &[Package],
+    global: (&str, &str),
+    prompt: &Prompt,
+) -> Result<HashMap<String, String>> {
+    let mut names: Vec<&str> = packages
+        .iter()
+        .filter_map(|pkg| pkg.overrides()?.workflow.as_deref())
+        .collect();
+    names.sort();
+    names.dedup();
+
+    let mut callers = HashMap::new();
+    for name in names {
+        let resolved = if name == global.0 {
+            global.1.to_string()
+        } else {
+            if !workflow::Workflow::load(name).is_ok_and(|w| w.only_called()) {
+                continue;
+            }
+            let crates: Vec<&str> = packages
+                .iter()
+                .filter(|pkg| pkg.overrides().and_then(|o| o.workflow.as_deref()) == Some(name))
+                .map(|pkg| pkg.name.as_str())
+                .collect();
+            say!("{} {}: {}", "⚙️  Workflow of".cyan(), crates.join(", "), name.yellow());
+            resolve_reusable(name, prompt)?
+        };
+        if resolved != name {
+            callers.insert(name.to_string(), resolved);
+        }
+    }
+    Ok(callers)
+}
+
+fn select_workflow(files: &[String], prompt: &Prompt) -> Result<String> {
     if files.is_empty() {
         bail!("No workflow files found in .github/workflows/. Specify one with -w.");
     }
@@ -128,20 +541,91 @@ fn select_workflow(files: &[String]) -> Result<String> {
         return Ok(files[0].clone());
     }
 
-    // Sort with release-plz.yml first if it exists
-    let mut sorted: Vec<_> = files.to_vec();
-    if let Some(pos) = sorted.iter().position(|f| f == "release-plz.yml") {
-        let release_plz = sorted.remove(pos);
-        sorted.insert(0, release_plz);
+    // Most likely publisher first; the sort is stable, so ties stay by name
+    let branch = default_branch();
+    let mut ranked: Vec<(&String, workflow::Rank)> = files
+        .iter()
+        .map(|f| {
+            let rank = workflow::Workflow::load(f).map(|w| w.rank(&branch)).unwrap_or_default();
+            (f, rank)
+        })
+        .collect();
+    ranked.sort_by_key(|(_, rank)| std::cmp::Reverse(rank.score));
+
+    let publishing: Vec<_> = ranked.iter().filter(|(_, rank)| rank.publishes).collect();
+    if let [(name, rank)] = publishing.as_slice() {
+        say!(
+            "{}",
+            format!("🎯 Picked the only workflow that publishes ({})", rank.reasons.join(", ")).dimmed()
+        );
+        return Ok(name.to_string());
     }
 
-    let selection = Select::with_theme(&ColorfulTheme::default())
-        .with_prompt("Select workflow")
-        .items(&sorted)
-        .default(0)
-        .interact()?;
+    let items: Vec<String> = ranked
+        .iter()
+        .map(|(name, rank)| {
+            let why = if rank.reasons.is_empty() {
+                "nothing suggests it publishes".to_string()
+            } else {
+                rank.reasons.join(", ")
+            };
+            format!("{}  {}", name, format!("({})", why).dimmed())
+        })
+        .collect();
+    let selection = prompt.select("Select workflow", &items, "-w <file>")?;
+
+    Ok(ranked[selection].0.clone())
+}
 
-    Ok(sorted[selection].clone())
+/// The branch `origin/HEAD` points at, or `main` when git can't tell.
+fn default_branch() -> String {
+    Command::new("git")
+        .args(["symbolic-ref", "--short", "refs/remotes/origin/HEAD"])
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .and_then(|output| String::from_utf8(output.stdout).ok())
+        .and_then(|branch| branch.trim().strip_prefix("origin/").map(str::to_string))
+        .unwrap_or_else(|| "main".to_string())
+}
+
+fn detect_gitlab_ci_file() -> Result<String> {
+    for name in [".gitlab-ci.yml", ".gitlab-ci.yaml"] {
+        if PathBuf::from(name).exists() {
+            return Ok(name.to_string());
+        }
+    }
+    bail!("No .gitlab-ci.yml found. Specify the CI file path with -w.");
+}
+
+/// Offers the environment declared by the workflow's publishing job, if any.
+fn suggest_environment(workflow_name: &str, prompt: &Prompt) -> Result<Option<String>> {
+    let Some(suggested) = workflow::Workflow::load(workflow_name)
+        .ok()
+        .and_then(|w| w.suggested_environment())
+    else {
+        return Ok(None);
+    };
+
+    // Not requiring an environment is the conservative default, so an
+    // unattended run skips the suggestion instead of failing on it.
+    if !prompt.can_confirm() {
+        say!(
+            "{}",
+            format!(
+                "{} runs in the `{}` environment; pass --environment {} to require it.",
+                workflow_name, suggested, suggested
+            )
+            .dimmed()
+        );
+        return Ok(None);
+    }
+
+    let question = format!(
+        "{} runs in the `{}` environment. Require it in the trusted publishing config?",
+        workflow_name, suggested
+    );
+    Ok(prompt.confirm(&question)?.then_some(suggested))
 }
 
 fn get_cache_path() -> PathBuf {
@@ -179,24 +663,12 @@ fn save_cache(cache: &TrustpubCache) -> Result<()> {
     Ok(())
 }
 
-fn read_token_from_credentials() -> Result<String> {
-    let path = get_cargo_credentials_path();
-    let contents = std::fs::read_to_string(&path)
-        .map_err(|e| eyre!("Could not read {}: {}", path.display(), e))?;
-
-    let creds: CargoCredentials = facet_toml::from_str(&contents)
-        .map_err(|e| eyre!("Could not parse {}: {}", path.display(), e))?;
-
-    creds
-        .registry
-        .and_then(|r| r.token)
-        .ok_or_else(|| eyre!("No token found in {}", path.display()))
-}
-
 #[derive(Facet, Debug)]
 struct CargoMetadata {
     packages: Vec<Package>,
     workspace_members: Vec<String>,
+    /// `[workspace.metadata]`
+    metadata: Option<Metadata>,
 }
 
 #[derive(Facet, Debug, Clone)]
@@ -208,37 +680,28 @@ struct Package {
     license: Option<String>,
     repository: Option<String>,
     publish: Option<Vec<String>>,
+    metadata: Option<Metadata>,
 }
 
-#[derive(Facet, Debug)]
-struct GithubConfigRequest {
-    github_config: GithubConfigInner,
-}
-
-#[derive(Facet, Debug)]
-struct GithubConfigInner {
-    #[facet(rename = "crate")]
-    crate_name: String,
-    repository_owner: String,
-    repository_name: String,
-    workflow_filename: String,
-}
-
-#[derive(Facet, Debug)]
-struct GithubConfigListResponse {
-    github_configs: Vec<GithubConfig>,
+impl Package {
+    /// `[package.metadata.tp]`, if the crate has one
+    fn overrides(&self) -> Option<&Overrides> {
+        self.metadata.as_ref()?.tp.as_ref()
+    }
 }
 
-#[derive(Facet, Debug)]
-struct GithubConfig {
-    #[facet(rename = "crate")]
-    crate_name: String,
-    repository_owner: String,
-    repository_name: String,
-    workflow_filename: String,
+/// The part of the workspace a run works on.
+struct Workspace {
+    packages: Vec<Package>,
+    /// `[workspace.metadata.tp]`
+    defaults: Overrides,
 }
 
-fn get_publishable_crates() -> Result<Vec<Package>> {
+/// Workspace crates that publish to the target registry, aren't skipped in
+/// `[package.metadata.tp]` and are selected by `--package`/`--exclude`.
+/// Crates with `publish = false` are left out silently; the others that are
+/// left out are listed as skipped and recorded in the report.
+fn load_workspace(registry: &Registry, selection: &PackageArgs, report: &mut Report) -> Result<Workspace> {
     let output = Command::new("cargo")
         .args(["metadata", "--format-version=1", "--no-deps"])
         .output()?;
@@ -256,24 +719,82 @@ fn get_publishable_crates() -> Result<Vec<Package>> {
     let workspace_member_ids: HashSet<&str> =
         metadata.workspace_members.iter().map(|s| s.as_str()).collect();
 
-    let publishable = metadata
+    // Cargo calls crates.io "crates-io" in `publish` lists
+    let target = match &registry.name {
+        _ if registry.is_crates_io() => Some(CRATES_IO),
+        name => name.as_deref(),
+    };
+
+    let (publishable, elsewhere): (Vec<Package>, Vec<Package>) = metadata
         .packages
         .into_iter()
-        .filter(|pkg| {
-            if !workspace_member_ids.contains(pkg.id.as_str()) {
-                return false;
-            }
-            match &pkg.publish {
-                Some(registries) if registries.is_empty() => false,
-                _ => true,
-            }
-        })
-        .collect();
+        .filter(|pkg| workspace_member_ids.contains(pkg.id.as_str()))
+        .filter(|pkg| pkg.publish.as_ref().is_none_or(|registries| !registries.is_empty()))
+        .partition(|pkg| match &pkg.publish {
+            None => true,
+            Some(registries) => target.is_some_and(|t| registries.iter().any(|r| r == t)),
+        });
+
+    if !elsewhere.is_empty() {
+        say!("{}", "⏭️  Skipped: publishes elsewhere".dimmed());
+        for pkg in &elsewhere {
+            let registries = pkg.publish.as_deref().unwrap_or_default().join(", ");
+            say!("   {} {} {}", "•".dimmed(), pkg.name.dimmed(), format!("→ {}", registries).dimmed());
+            let mut p = PackageReport::new(pkg);
+            p.result = Some("publishes-elsewhere".to_string());
+            report.packages.push(p);
+        }
+        say!();
+    }
+
+    let (skipped, publishable): (Vec<Package>, Vec<Package>) =
+        publishable.into_iter().partition(|pkg| pkg.overrides().is_some_and(Overrides::skip));
+    if !skipped.is_empty() {
+        say!("{}", "⏭️  Skipped: skip = true in [package.metadata.tp]".dimmed());
+        for pkg in &skipped {
+            say!("   {} {}", "•".dimmed(), pkg.name.dimmed());
+            let mut p = PackageReport::new(pkg);
+            p.result = Some("skipped-by-metadata".to_string());
+            report.packages.push(p);
+        }
+        say!();
+    }
+
+    let defaults = metadata.metadata.and_then(|m| m.tp).unwrap_or_default();
+    let invalid_repository = |o: &Overrides| o.repository.is_some() && o.repository().is_none();
+    if invalid_repository(&defaults) {
+        bail!("`repository` in [workspace.metadata.tp] must look like \"owner/repo\"");
+    }
+    if let Some(pkg) = publishable.iter().find(|pkg| pkg.overrides().is_some_and(invalid_repository)) {
+        bail!("`repository` in [package.metadata.tp] of {} must look like \"owner/repo\"", pkg.name);
+    }
+
+    let (selected, excluded) = selection.apply(publishable)?;
+    if !excluded.is_empty() {
+        say!(
+            "{}",
+            format!(
+                "⏭️  Skipped: not selected by --package/--exclude ({} crate{})",
+                excluded.len(),
+                if excluded.len() == 1 { "" } else { "s" }
+            )
+            .dimmed()
+        );
+        say!();
+        for pkg in &excluded {
+            let mut p = PackageReport::new(pkg);
+            p.result = Some("not-selected".to_string());
+            report.packages.push(p);
+        }
+    }
 
-    Ok(publishable)
+    Ok(Workspace {
+        packages: selected,
+        defaults,
+    })
 }
 
-fn publish_skeleton(pkg: &Package, token: &str) -> Result<()> {
+fn publish_skeleton(pkg: &Package, token: &str, registry: &Registry) -> Result<()> {
     let tmp_dir = std::env::temp_dir().join(format!("tp-skeleton-{}", pkg.name));
 
     if tmp_dir.exists() {
@@ -307,11 +828,25 @@ license = "{}"
     std::fs::create_dir_all(&src_dir)?;
     std::fs::write(src_dir.join("lib.rs"), "//! Placeholder crate for trusted publishing setup.\n")?;
 
-    let status = Command::new("cargo")
-        .args(["publish", "--allow-dirty"])
-        .env("CARGO_REGISTRY_TOKEN", token)
-        .current_dir(&tmp_dir)
-        .status()?;
+    let mut cmd = Command::new("cargo");
+    cmd.args(["publish", "--allow-dirty"]).current_dir(&tmp_dir);
+    match &registry.name {
+        _ if registry.is_crates_io() => {
+            cmd.env("CARGO_REGISTRY_TOKEN", token);
+        }
+        Some(name) => {
+            cmd.args(["--registry", name])
+                .env(format!("CARGO_REGISTRIES_{}_TOKEN", registry::env_key(name)), token);
+        }
+        None => {
+            cmd.args(["--index", &format!("sparse+{}", registry.index_url), "--token", token]);
+        }
+    }
+    if report::is_json() {
+        // stdout is reserved for the JSON report
+        cmd.stdout(std::io::stderr());
+    }
+    let status = cmd.status()?;
 
     std::fs::remove_dir_all(&tmp_dir)?;
 
@@ -322,385 +857,976 @@ license = "{}"
     Ok(())
 }
 
-fn ask_yes_no(prompt: &str) -> bool {
-    Confirm::with_theme(&ColorfulTheme::default())
-        .with_prompt(prompt)
-        .default(true)
-        .interact()
-        .unwrap_or(false)
-}
-
-fn sparse_index_path(name: &str) -> String {
-    let name = name.to_lowercase();
-    match name.len() {
-        1 => format!("1/{}", name),
-        2 => format!("2/{}", name),
-        3 => format!("3/{}/{}", &name[..1], name),
-        _ => format!("{}/{}/{}", &name[..2], &name[2..4], name),
+fn progress_bar(len: u64) -> ProgressBar {
+    let pb = ProgressBar::new(len);
+    pb.set_style(
+        ProgressStyle::default_bar()
+            .template("{msg} [{bar:30}] {pos}/{len}")
+            .unwrap()
+            .progress_chars("=> "),
+    );
+    if report::is_json() {
+        pb.set_draw_target(ProgressDrawTarget::hidden());
     }
+    pb
 }
 
-fn check_local_sparse_index(name: &str) -> bool {
-    let cargo_home = dirs::home_dir()
-        .unwrap_or_default()
-        .join(".cargo")
-        .join("registry")
-        .join("index");
+/// Checks which crates exist on the registry, with a progress bar. Fails if any
+/// check does: a failed check must not pass for "not published".
+async fn check_published(client: &ApiClient, packages: &[Package]) -> Result<HashMap<String, bool>> {
+    let pb = progress_bar(packages.len() as u64);
+    pb.set_message(format!("Checking {}", client.label()));
 
-    // Find any index.crates.io-* directory
-    if let Ok(entries) = std::fs::read_dir(&cargo_home) {
-        for entry in entries.flatten() {
-            let dir_name = entry.file_name();
-            if dir_name.to_string_lossy().starts_with("index.crates.io-") {
-                let cache_path = entry.path().join(sparse_index_path(name));
-                if cache_path.exists() {
-                    return true;
-                }
-            }
+    let checks: Vec<_> = stream::iter(packages.iter().map(|pkg| {
+        let pb = &pb;
+        async move {
+            let exists = crate_exists(client, &pkg.name).await;
+            pb.inc(1);
+            (pkg, exists)
         }
+    }))
+    .buffer_unordered(20)
+    .collect()
+    .await;
+    pb.finish_and_clear();
+
+    let mut published = HashMap::with_capacity(checks.len());
+    for (pkg, exists) in checks {
+        let exists = exists.wrap_err_with(|| format!("Could not check whether {} is on {}", pkg.name, client.label()))?;
+        published.insert(pkg.name.clone(), exists);
     }
-    false
+    Ok(published)
 }
 
-async fn crate_exists(client: &Client, name: &str) -> Result<bool> {
-    // Check local cache first
-    if check_local_sparse_index(name) {
-        return Ok(true);
+fn resolve_repo(target: &TargetArgs, workspace: &Overrides, config: &Overrides) -> Result<(Forge, String, String)> {
+    match pinned_repo(target, workspace, config)? {
+        Some(repo) => Ok(repo),
+        None => detected_repo(target),
     }
+}
 
-    // Fall back to network request
-    let url = format!("https://index.crates.io/{}", sparse_index_path(name));
-    let res = client
-        .get(&url)
-        .header("User-Agent", USER_AGENT)
-        .header("Accept", "text/plain")
-        .send()
-        .await?;
-    Ok(res.status().is_success())
+/// The repository given on the command line, or else in
+/// `[workspace.metadata.tp]` or a config file; `None` when it has to be
+/// detected.
+fn pinned_repo(target: &TargetArgs, workspace: &Overrides, config: &Overrides) -> Result<Option<(Forge, String, String)>> {
+    let forge = if target.gitlab { Forge::Gitlab } else { Forge::Github };
+    let pinned = match (workspace.repository(), config.repository()) {
+        (Some(repository), _) => Some((repository, "📌 Repo from [workspace.metadata.tp]:")),
+        (None, Some(repository)) => Some((repository, "📌 Repo from config:")),
+        (None, None) => None,
+    };
+    match (&target.owner, &target.repo, pinned) {
+        (Some(o), Some(r), _) => Ok(Some((forge, o.clone(), r.clone()))),
+        (None, None, Some(((o, r), label))) => {
+            say!("{} {}/{}", label.cyan(), o.green(), r.green());
+            Ok(Some((forge, o.to_string(), r.to_string())))
+        }
+        (None, None, None) => Ok(None),
+        (Some(_), None, _) => bail!("If you specify owner, you must also specify repo"),
+        (None, Some(_), _) => bail!("If you specify repo, you must also specify owner"),
+    }
+}
+
+/// The repository of the git remote, on GitLab if `--gitlab` says so.
+fn detected_repo(target: &TargetArgs) -> Result<(Forge, String, String)> {
+    let (forge, o, r) = detect_repo()?;
+    let forge = if target.gitlab { Forge::Gitlab } else { forge };
+    say!(
+        "{} {}/{} {}",
+        "🔍 Detected repo:".cyan(),
+        o.green(),
+        r.green(),
+        format!("({})", forge.host()).dimmed()
+    );
+    Ok((forge, o, r))
 }
 
-async fn list_trustpub_github_configs(
-    client: &Client,
+/// Checks the token before anything is done with it, so a bad one fails
+/// here rather than halfway through. Where the registry says who the token
+/// acts as, also checks that account may publish at all, and returns its
+/// login. crates.io never says for an API token, so there the token is tried
+/// on `crate_name`, one of the workspace's crates: the registry checks the
+/// token before looking the crate up, so it needn't be published yet.
+async fn preflight(
+    client: &ApiClient,
     token: &str,
-    crates: &[Package],
-) -> Result<Vec<GithubConfig>> {
-    // Query configs for each crate in parallel
-    let results: Vec<_> = stream::iter(crates.iter().map(|pkg| {
-        let client = client;
-        let crate_name = &pkg.name;
-        async move {
-            let url = format!(
-                "{}/api/v1/trusted_publishing/github_configs?crate={}",
-                BASE_URL,
-                crate_name
-            );
+    registry: &Registry,
+    crate_name: &str,
+    report: &mut Report,
+) -> Result<Option<String>> {
+    let user = if registry.is_crates_io() {
+        None
+    } else {
+        current_user(client, token).await?
+    };
+    let Some(user) = user else {
+        check_token(client, token, crate_name).await?;
+        say!("{} {}", "👤 Token accepted by".cyan(), registry.label().green());
+        say!(
+            "{}",
+            "   It needs the publish-new, publish-update and trusted-publishing scopes.".dimmed()
+        );
+        say!();
+        return Ok(None);
+    };
 
-            let res = client
-                .get(&url)
-                .header("User-Agent", USER_AGENT)
-                .header("Authorization", token)
-                .send()
-                .await?;
-
-            if !res.status().is_success() {
-                let status = res.status();
-                let text = res.text().await?;
-                bail!("Failed to list configurations for {}: {}: {}", crate_name, status, text);
-            }
+    match &user.name {
+        Some(name) => say!("{} {} ({})", "👤 Acting as:".cyan(), user.login.green(), name),
+        None => say!("{} {}", "👤 Acting as:".cyan(), user.login.green()),
+    }
+    say!();
+    report.user = Some(user.login.clone());
+
+    if !user.can_publish() {
+        return Err(AuthError(format!(
+            "{} has no verified email address, which {} requires before publishing; verify one and run tp again",
+            user.login,
+            registry.label()
+        ))
+        .into());
+    }
+    Ok(Some(user.login))
+}
 
-            let body = res.text().await?;
-            let response: GithubConfigListResponse = from_str(&body)?;
-            Ok::<_, color_eyre::eyre::Error>(response.github_configs)
-        }
-    }))
-    .buffer_unordered(20)
-    .collect()
-    .await;
+fn resolve_token(token_env: Option<&str>, registry: &Registry, report: &mut Report) -> Result<String> {
+    let token = credentials::find_token(token_env, registry).map_err(|e| AuthError(e.to_string()))?;
+    say!("{} {}", "🔑 Token from".dimmed(), token.source.dimmed());
+    report.token_source = Some(token.source);
+    Ok(token.secret)
+}
 
-    // Flatten all configs into a single vector
-    let mut all_configs = Vec::new();
-    for result in results {
-        all_configs.extend(result?);
+/// Subcommand names, as the command line spells them
+const SUBCOMMANDS: &[&str] = &["setup", "sync", "status", "remove", "lock", "owners", "workflow"];
+
+/// The bare words a subcommand can be followed by: its own subcommands, or
+/// `None` when it takes an owner and repo.
+fn bare_words(subcommand: &str) -> Option<&'static [&'static str]> {
+    match subcommand {
+        "lock" | "owners" => Some(&[]),
+        "workflow" => Some(&["init", "migrate"]),
+        _ => None,
     }
-    Ok(all_configs)
 }
 
-async fn create_trustpub_github_config(
-    client: &Client,
-    token: &str,
-    config: &GithubConfigRequest,
-) -> Result<()> {
-    let url = format!("{}/api/v1/trusted_publishing/github_configs", BASE_URL);
-    let body = to_string(config)?;
-
-    let res = client
-        .post(&url)
-        .header("User-Agent", USER_AGENT)
-        .header("Content-Type", "application/json")
-        .header("Authorization", token)
-        .body(body)
-        .send()
-        .await?;
-
-    if !res.status().is_success() {
-        let status = res.status();
-        let text = res.text().await?;
-        bail!("{}: {}", status, text);
+/// The command line with `setup` spelled out when no subcommand is given, so
+/// `tp <owner> <repo> -w release.yml` keeps meaning what it did before `tp`
+/// had subcommands. A subcommand's name followed by a bare word it can't
+/// take is an owner, as in `tp lock my-repo`; an owner named after one that
+/// takes an owner and repo needs `setup` spelled out. `--help`, `--version`
+/// and `--completions` on their own still go to the top level.
+fn cli_args(mut args: Vec<String>) -> Vec<String> {
+    let top_level = match args.first() {
+        Some(first) if SUBCOMMANDS.contains(&first.as_str()) => {
+            match (bare_words(first), args.get(1).filter(|next| !next.starts_with('-'))) {
+                (Some(words), Some(next)) => words.contains(&next.as_str()),
+                _ => true,
+            }
+        }
+        Some(first) => matches!(first.as_str(), "-h" | "--help" | "-V" | "--version" | "--completions"),
+        None => false,
+    };
+    if !top_level {
+        args.insert(0, "setup".to_string());
     }
-
-    Ok(())
+    args
 }
 
 #[tokio::main]
-async fn main() -> Result<()> {
+async fn main() -> ExitCode {
+    match run().await {
+        Ok(outcome) => ExitCode::from(outcome.code()),
+        Err(e) => {
+            eprintln!("Error: {:?}", e);
+            ExitCode::from(exit::classify(&e).0)
+        }
+    }
+}
+
+async fn run() -> Result<Outcome> {
     color_eyre::install()?;
 
-    let args: Args = figue::from_std_args().unwrap();
+    let argv = cli_args(std::env::args().skip(1).collect());
+    let argv: Vec<&str> = argv.iter().map(String::as_str).collect();
+    let args: Args = match figue::from_slice(&argv) {
+        Ok(args) => args,
+        Err(e) => bail!("{}", e),
+    };
 
-    // Print cache location upfront
-    println!("{} {}\n", "📁 Cache:".dimmed(), get_cache_path().display().dimmed());
+    let mut command = args.command.unwrap_or(Subcommand::Setup {
+        target: TargetArgs::default(),
+        registry: RegistryArgs::default(),
+        prompt: PromptArgs::default(),
+        output: OutputArgs::default(),
+        dry_run: false,
+        lock: false,
+    });
+
+    let (name, output) = match &command {
+        Subcommand::Setup { output, .. } => ("setup", output),
+        Subcommand::Sync { output, .. } => ("sync", output),
+        Subcommand::Status { output, .. } => ("status", output),
+        Subcommand::Remove { output, .. } => ("remove", output),
+        Subcommand::Lock { output, .. } => ("lock", output),
+        Subcommand::Owners { output, .. } => ("owners", output),
+        Subcommand::Workflow {
+            command: WorkflowCommand::Init { output, .. },
+        } => ("workflow init", output),
+        Subcommand::Workflow {
+            command: WorkflowCommand::Migrate { output, .. },
+        } => ("workflow migrate", output),
+    };
+    report::init(output)?;
+    let mut report = Report::new(name);
 
-    let (owner, repo) = match (&args.owner, &args.repo) {
-        (Some(o), Some(r)) => (o.clone(), r.clone()),
-        (None, None) => {
-            let (o, r) = detect_github_repo()?;
-            println!("{} {}/{}", "🔍 Detected repo:".cyan(), o.green(), r.green());
-            (o, r)
+    let config = match Config::load() {
+        Ok(config) => config,
+        Err(e) => return report.finish(Err(e)),
+    };
+    let mut origins = Vec::new();
+    // What config files say about the repository, workflow and environment;
+    // these go below [workspace.metadata.tp]
+    let mut defaults = Overrides::default();
+    match &mut command {
+        Subcommand::Setup { target, registry, .. }
+        | Subcommand::Sync { target, registry, .. }
+        | Subcommand::Status { target, registry, .. }
+        | Subcommand::Workflow {
+            command: WorkflowCommand::Init { target, registry, .. },
+        } => {
+            defaults = match config.defaults(target, &mut origins) {
+                Ok(defaults) => defaults,
+                Err(e) => return report.finish(Err(e)),
+            };
+            config.apply_target(target, &mut origins);
+            config.apply_registry(registry, &mut origins);
+        }
+        // The positional arguments and -w of `remove` are filters on what
+        // to delete, so pinned values don't apply to them.
+        Subcommand::Remove {
+            token_env, registry, ..
+        } => {
+            config.fill_token_env(token_env, &mut origins);
+            config.apply_registry(registry, &mut origins);
+        }
+        Subcommand::Lock {
+            token_env,
+            packages,
+            registry,
+            ..
+        } => {
+            config.fill_token_env(token_env, &mut origins);
+            config.apply_packages(packages, &mut origins);
+            config.apply_registry(registry, &mut origins);
         }
-        (Some(_), None) => bail!("If you specify owner, you must also specify repo"),
-        (None, Some(_)) => bail!("If you specify repo, you must also specify owner"),
+        Subcommand::Owners {
+            crate_owner,
+            token_env,
+            packages,
+            registry,
+            ..
+        } => {
+            config.apply_crate_owners(crate_owner, &mut origins);
+            config.fill_token_env(token_env, &mut origins);
+            config.apply_packages(packages, &mut origins);
+            config.apply_registry(registry, &mut origins);
+        }
+        Subcommand::Workflow {
+            command: WorkflowCommand::Migrate { workflow, .. },
+        } => {
+            config.fill_workflow(workflow, &mut origins);
+        }
+    }
+    if !config.is_empty() {
+        config::print_origins(&origins);
+    }
+
+    // Rewriting a workflow doesn't talk to any registry
+    let no_registry = RegistryArgs::default();
+    let registry = match &command {
+        Subcommand::Setup { registry, .. }
+        | Subcommand::Sync { registry, .. }
+        | Subcommand::Status { registry, .. }
+        | Subcommand::Remove { registry, .. }
+        | Subcommand::Lock { registry, .. }
+        | Subcommand::Owners { registry, .. }
+        | Subcommand::Workflow {
+            command: WorkflowCommand::Init { registry, .. },
+        } => registry,
+        Subcommand::Workflow {
+            command: WorkflowCommand::Migrate { .. },
+        } => &no_registry,
+    };
+    let registry = match Registry::resolve(registry) {
+        Ok(registry) => registry,
+        Err(e) => return report.finish(Err(e)),
     };
+    if !registry.is_crates_io() {
+        say!("{} {}", "🏛️  Registry:".cyan(), registry.label().green());
+        report.registry = Some(registry.label());
+    }
 
-    let workflow = match &args.workflow {
-        Some(w) => {
-            println!("{} {}", "⚙️  Workflow:".cyan(), w.yellow());
-            w.clone()
+    let result = match command {
+        Subcommand::Setup {
+            target,
+            prompt,
+            dry_run,
+            lock,
+            ..
+        } => setup(&target, &defaults, &registry, &Prompt::new(&prompt), &mut report, dry_run, false, lock).await,
+        Subcommand::Sync {
+            target,
+            prompt,
+            dry_run,
+            prune,
+            lock,
+            ..
+        } => setup(&target, &defaults, &registry, &Prompt::new(&prompt), &mut report, dry_run, prune, lock).await,
+        Subcommand::Status { target, check, .. } => {
+            status::status(&target, &defaults, &registry, &mut report, check).await
         }
-        None => {
-            let files = detect_workflow_files()?;
-            let w = select_workflow(&files)?;
-            println!("{} {}", "⚙️  Workflow:".cyan(), w.yellow());
-            w
+        Subcommand::Remove {
+            owner,
+            repo,
+            workflow,
+            token_env,
+            packages,
+            all,
+            dry_run,
+            prompt,
+            ..
+        } => {
+            let filter = remove::RemoveFilter {
+                owner,
+                repo,
+                workflow,
+                packages,
+            };
+            let prompt = Prompt::new(&prompt);
+            remove::remove(&filter, token_env.as_deref(), &registry, &prompt, &mut report, all, dry_run).await
         }
+        Subcommand::Lock {
+            token_env,
+            packages,
+            dry_run,
+            prompt,
+            ..
+        } => {
+            let prompt = Prompt::new(&prompt);
+            lock::lock(&packages, token_env.as_deref(), &registry, &prompt, &mut report, dry_run).await
+        }
+        Subcommand::Owners {
+            crate_owner,
+            remove_extra,
+            token_env,
+            packages,
+            dry_run,
+            prompt,
+            ..
+        } => {
+            let desired = owners::DesiredOwners {
+                owners: crate_owner,
+                remove_extra,
+            };
+            let prompt = Prompt::new(&prompt);
+            owners::owners(&desired, &packages, token_env.as_deref(), &registry, &prompt, &mut report, dry_run).await
+        }
+        Subcommand::Workflow {
+            command:
+                WorkflowCommand::Init {
+                    template,
+                    force,
+                    mut target,
+                    prompt,
+                    dry_run,
+                    ..
+                },
+        } => {
+            let prompt = Prompt::new(&prompt);
+            init::init(template.as_deref(), &mut target, &defaults, &registry, &prompt, &mut report, force, dry_run)
+                .await
+        }
+        Subcommand::Workflow {
+            command:
+                WorkflowCommand::Migrate {
+                    workflow,
+                    prompt,
+                    dry_run,
+                    ..
+                },
+        } => migrate::migrate(workflow.as_deref(), &Prompt::new(&prompt), &mut report, dry_run),
     };
-    println!();
 
-    let token = if let Some(env_var) = &args.token_env {
-        std::env::var(env_var).map_err(|_| eyre!("Set {} environment variable", env_var))?
-    } else {
-        read_token_from_credentials()?
-    };
+    report.finish(result)
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn setup(
+    target: &TargetArgs,
+    config: &Overrides,
+    registry: &Registry,
+    prompt: &Prompt,
+    report: &mut Report,
+    dry_run: bool,
+    prune: bool,
+    lock: bool,
+) -> Result<Outcome> {
+    report.dry_run = dry_run;
+
+    // Print cache location upfront
+    say!("{} {}\n", "📁 Cache:".dimmed(), get_cache_path().display().dimmed());
 
-    let packages = get_publishable_crates()?;
-    println!(
+    let workspace = load_workspace(registry, &target.packages, report)?;
+    let chosen = target.over(&workspace.defaults.or(config));
+
+    let (forge, owner, repo) = resolve_repo(target, &workspace.defaults, config)?;
+    report.forge = Some(forge.id().to_string());
+    report.owner = Some(owner.clone());
+    report.repo = Some(repo.clone());
+
+    let token = resolve_token(target.token_env.as_deref(), registry, report)?;
+
+    let packages = workspace.packages;
+    report.packages.extend(packages.iter().map(PackageReport::new));
+    say!(
         "📦 Found {} publishable crate{}\n",
         packages.len().to_string().bright_white().bold(),
         if packages.len() == 1 { "" } else { "s" }
     );
 
     if packages.is_empty() {
-        println!("{}", "No publishable crates found.".yellow());
-        return Ok(());
+        say!("{}", "No publishable crates found.".yellow());
+        return Ok(Outcome::NothingToDo);
     }
 
-    let client = Client::new();
+    let client = ApiClient::new(registry).await?;
 
-    let pb = ProgressBar::new(packages.len() as u64);
-    pb.set_style(
-        ProgressStyle::default_bar()
-            .template("{msg} [{bar:30}] {pos}/{len}")
-            .unwrap()
-            .progress_chars("=> "),
-    );
-    pb.set_message("Checking crates.io");
+    // A failed check must not pass for "never published": that would offer to
+    // publish a skeleton over a crate that exists.
+    let published = check_published(&client, &packages).await?;
+    let results: Vec<(&Package, bool)> = packages.iter().map(|pkg| (pkg, published[&pkg.name])).collect();
 
-    // Check crate existence in parallel (up to 20 concurrent requests)
-    let results: Vec<_> = stream::iter(packages.iter().map(|pkg| {
-        let client = &client;
-        let pb = &pb;
-        async move {
-            let exists = crate_exists(client, &pkg.name).await.unwrap_or(false);
-            pb.inc(1);
-            (pkg, exists)
+    for (pkg, exists) in &results {
+        if let Some(p) = report.package_mut(&pkg.name) {
+            p.published = Some(*exists);
         }
-    }))
-    .buffer_unordered(20)
-    .collect()
-    .await;
+    }
+
+    // Before any question is asked, so a bad token doesn't waste the answers
+    let first_published = results.iter().find(|(_, exists)| *exists).map(|(pkg, _)| pkg.name.as_str());
+    let login = preflight(&client, &token, registry, first_published.unwrap_or(&packages[0].name), report).await?;
+
+    let picked = match chosen.workflow {
+        Some(w) => w.clone(),
+        None => match forge {
+            Forge::Github => select_workflow(&detect_workflow_files()?, prompt)?,
+            Forge::Gitlab => detect_gitlab_ci_file()?,
+        },
+    };
+    let workflow = match forge {
+        Forge::Github => resolve_reusable(&picked, prompt)?,
+        Forge::Gitlab => picked.clone(),
+    };
+    say!("{} {}", "⚙️  Workflow:".cyan(), workflow.yellow());
+    // Crates naming their own workflow may name a reusable one just the same
+    let callers = match forge {
+        Forge::Github => resolve_crate_workflows(&packages, (&picked, &workflow), prompt)?,
+        Forge::Gitlab => HashMap::new(),
+    };
+
+    let environment = match chosen.environment {
+        Some(env) => Some(env),
+        None if forge == Forge::Github => suggest_environment(&workflow, prompt)?,
+        None => None,
+    };
+    if let Some(env) = &environment {
+        say!("{} {}", "🌱 Environment:".cyan(), env.yellow());
+    }
+    say!();
+
+    report.workflow = Some(workflow.clone());
+    report.environment = environment.clone();
+
+    // Only a published crate can belong to someone else
+    let published: Vec<_> = results.iter().filter(|(_, exists)| *exists).map(|(pkg, _)| *pkg).collect();
+    let mut not_owned = owners::check(&client, &token, login.as_deref(), &published, report).await?;
 
     let unpublished: Vec<_> = results
         .into_iter()
         .filter(|(_, exists)| !exists)
         .map(|(pkg, _)| pkg)
         .collect();
-    pb.finish_and_clear();
 
     if !unpublished.is_empty() {
-        println!("\n{}", "⚠️  The following crates have never been published to crates.io:".yellow());
+        say!(
+            "\n{}",
+            format!("⚠️  The following crates have never been published to {}:", registry.label()).yellow()
+        );
         for pkg in &unpublished {
-            println!("  {} {}", "•".dimmed(), pkg.name.bright_white());
+            say!("  {} {}", "•".dimmed(), pkg.name.bright_white());
         }
 
-        if args.dry_run {
-            println!("\n{}", "(dry run) Would publish skeleton crates to reserve names".dimmed());
-        } else if ask_yes_no("Publish skeleton crates to reserve these names?") {
-            println!();
+        if dry_run {
+            say!("\n{}", "(dry run) Would publish skeleton crates to reserve names".dimmed());
+        } else if prompt.confirm("Publish skeleton crates to reserve these names?")? {
+            say!();
             for pkg in &unpublished {
-                print!("  Publishing {}... ", pkg.name.cyan());
-                stdout().flush().unwrap();
-                match publish_skeleton(pkg, &token) {
-                    Ok(()) => println!("{}", "✓".green()),
+                if report::is_json() {
+                    eprint!("  Publishing {}... ", pkg.name.cyan());
+                } else {
+                    print!("  Publishing {}... ", pkg.name.cyan());
+                    stdout().flush().unwrap();
+                }
+                match publish_skeleton(pkg, &token, registry) {
+                    Ok(()) => {
+                        say!("{}", "✓".green());
+                        if let Some(p) = report.package_mut(&pkg.name) {
+                            p.published = Some(true);
+                        }
+                    }
                     Err(e) => {
-                        println!("{} {}", "✗".red(), e.to_string().red());
+                        say!("{} {}", "✗".red(), e.to_string().red());
                         bail!("Failed to publish skeleton for {}", pkg.name);
                     }
                 }
                 tokio::time::sleep(Duration::from_secs(10)).await;
             }
-            println!();
+            say!(
+                "  {}",
+                "The new crates are owned by you alone; run `tp owners` to give them the workspace's other owners.".dimmed()
+            );
+            say!();
         } else {
-            println!("\n{}", "Aborted.".yellow());
-            std::process::exit(1);
+            say!("\n{}", "Aborted.".yellow());
+            return Ok(Outcome::Aborted);
         }
     } else {
-        println!("{}", "✓ All crates exist on crates.io.".green());
+        say!("{}", format!("✓ All crates exist on {}.", registry.label()).green());
     }
 
-    // List existing configurations from crates.io
-    println!("\n{}", "🔍 Checking existing configurations...".cyan());
-    let existing_configs = list_trustpub_github_configs(&client, &token, &packages).await?;
+    let packages: Vec<_> = packages
+        .into_iter()
+        .filter(|pkg| !not_owned.contains(&pkg.name))
+        .map(|mut pkg| {
+            let own = pkg.metadata.as_mut().and_then(|m| m.tp.as_mut()).and_then(|tp| tp.workflow.as_mut());
+            if let Some(own) = own.filter(|own| callers.contains_key(own.as_str())) {
+                *own = callers[own.as_str()].clone();
+            }
+            pkg
+        })
+        .collect();
+    if packages.is_empty() {
+        say!("\n{}", "None of the crates are yours to configure.".yellow());
+        return Ok(Outcome::NothingToDo);
+    }
 
-    // Build a set of already-configured (owner, repo, crate) tuples
-    let already_configured: HashSet<(String, String, String)> = existing_configs
+    // List existing configurations from crates.io
+    say!("\n{}", "🔍 Checking existing configurations...".cyan());
+    // Only the forge being configured matters, unless pruning, which deletes
+    // whatever else the crates have
+    let forges: &[Forge] = if prune { &Forge::ALL } else { &[forge] };
+    let listing = list_trustpub_configs(&client, &token, &packages, forges).await?;
+    owners::print_refused(&listing.not_owned, client.label(), report);
+    let existing_configs = listing.configs;
+    let packages: Vec<_> = packages
         .into_iter()
-        .map(|cfg| (cfg.repository_owner, cfg.repository_name, cfg.crate_name))
+        .filter(|pkg| !listing.not_owned.contains(&pkg.name))
         .collect();
+    not_owned.extend(listing.not_owned);
+    if packages.is_empty() {
+        say!("\n{}", "None of the crates are yours to configure.".yellow());
+        return Ok(Outcome::NothingToDo);
+    }
+
+    let desired = sync::Desired {
+        forge,
+        owner: &owner,
+        repo: &repo,
+        workflow: Some(&workflow),
+        environment: environment.as_deref(),
+    };
+    let plan = sync::plan(&packages, &existing_configs, &desired, prune);
+
+    for cfg in &plan.correct {
+        report.add_config(cfg, Some("correct"));
+        if let Some(p) = report.package_mut(&cfg.crate_name) {
+            p.configured = true;
+            p.result = Some("already-configured".to_string());
+        }
+    }
+    for cfg in &plan.stray {
+        report.add_config(cfg, Some("stray"));
+    }
+    for pkg in &plan.drifted {
+        if let Some(p) = report.package_mut(&pkg.name) {
+            p.result = Some("drifted".to_string());
+        }
+    }
 
     let mut cache = load_cache();
 
     // Update cache based on actual configurations from crates.io
-    for pkg in &packages {
-        if already_configured.contains(&(owner.clone(), repo.clone(), pkg.name.clone())) {
-            cache.configured.insert(cache_key(&owner, &repo, &pkg.name));
-        }
+    for cfg in &plan.correct {
+        cache.configured.insert(cache_key(&cfg.owner, &cfg.repo, &cfg.crate_name));
     }
 
-    // Filter out already-configured crates
-    let to_configure: Vec<_> = packages
-        .iter()
-        .filter(|pkg| !already_configured.contains(&(owner.clone(), repo.clone(), pkg.name.clone())))
-        .collect();
+    let mut to_configure = plan.to_add.clone();
+    let mut to_prune: Vec<&TrustpubConfig> = if prune { plan.stray.clone() } else { Vec::new() };
 
-    if to_configure.is_empty() {
-        println!(
+    if to_configure.is_empty() && to_prune.is_empty() {
+        say!(
             "\n{} All {} crates already have trusted publishing configured.",
             "✓".green(),
             packages.len()
         );
+        sync::print_drifted(&plan.drifted);
+        sync::print_stray(&plan.stray, prune);
         // Save updated cache
         if let Err(e) = save_cache(&cache) {
             eprintln!("{} could not save cache: {}", "⚠️  Warning:".yellow(), e);
         }
-        return Ok(());
+        if lock {
+            return lock::lock_published(&client, &token, &packages, prompt, report, dry_run)
+                .await
+                .map(|outcome| Outcome::Success.and(outcome));
+        }
+        return Ok(Outcome::Success);
     }
 
-    println!(
-        "\n🔐 Will configure trusted publishing for {} crate{}:",
-        to_configure.len().to_string().bright_white().bold(),
-        if to_configure.len() == 1 { "" } else { "s" }
-    );
-    println!("   {} {}/{}", "Repository:".dimmed(), owner.green(), repo.green());
-    println!("   {} {}", "Workflow:".dimmed(), workflow.yellow());
-    println!("   {}", "Crates:".dimmed());
-    for pkg in &to_configure {
-        println!("     {} {}", "•".dimmed(), pkg.name.cyan());
+    if !to_configure.is_empty() {
+        say!(
+            "\n🔐 Will configure trusted publishing for {} crate{}:",
+            to_configure.len().to_string().bright_white().bold(),
+            if to_configure.len() == 1 { "" } else { "s" }
+        );
+        say!(
+            "   {} {}/{} {}",
+            "Repository:".dimmed(),
+            owner.green(),
+            repo.green(),
+            format!("({})", forge.host()).dimmed()
+        );
+        say!("   {} {}", "Workflow:".dimmed(), workflow.yellow());
+        if let Some(env) = &environment {
+            say!("   {} {}", "Environment:".dimmed(), env.yellow());
+        }
+        say!("   {}", "Crates:".dimmed());
+        for pkg in &to_configure {
+            let d = desired.for_package(pkg);
+            if d == desired {
+                say!("     {} {}", "•".dimmed(), pkg.name.cyan());
+            } else {
+                // Overridden in [package.metadata.tp]
+                say!(
+                    "     {} {} {} {}{}",
+                    "•".dimmed(),
+                    pkg.name.cyan(),
+                    format!("{}/{}", d.owner, d.repo).green(),
+                    d.workflow.unwrap_or_default().yellow(),
+                    d.environment.map(|e| format!(" [{}]", e)).unwrap_or_default().yellow()
+                );
+            }
+        }
     }
-    if packages.len() > to_configure.len() {
-        println!(
+    if !plan.correct.is_empty() {
+        say!(
             "   {}",
-            format!("({} crates already configured, skipped)", packages.len() - to_configure.len()).dimmed()
+            format!("({} crates already configured, skipped)", plan.correct.len()).dimmed()
         );
     }
-    println!();
+    sync::print_drifted(&plan.drifted);
+    sync::print_stray(&plan.stray, prune);
+
+    // Look inside the workflows before configuring crates against them
+    if forge == Forge::Github {
+        let mut workflows: Vec<&str> = to_configure.iter().filter_map(|pkg| desired.for_package(pkg).workflow).collect();
+        workflows.sort();
+        workflows.dedup();
+        for name in workflows {
+            say!();
+            let Ok(wf) = workflow::Workflow::load(name) else {
+                say!(
+                    "{} {}",
+                    "🩺 Workflow check:".cyan(),
+                    format!("skipped, {} isn't in {}", name, workflow::workflows_dir().display()).dimmed()
+                );
+                continue;
+            };
+            let findings = wf.lint();
+            workflow::print_findings(name, &findings);
+            report.workflow_checks.extend(findings.into_iter().map(|f| CheckReport {
+                workflow: name.to_string(),
+                check: f.check.to_string(),
+                passed: f.passed,
+                message: f.message,
+            }));
+        }
+    }
+    say!();
+
+    // Last chance to leave some crates out; they are reported as skipped.
+    // A crate left out keeps the configurations a prune would have replaced.
+    let mut deselected = Vec::new();
+    if !dry_run && to_configure.len() > 1 {
+        let names: Vec<String> = to_configure
+            .iter()
+            .map(|pkg| {
+                let replaced: Vec<String> = to_prune
+                    .iter()
+                    .filter(|cfg| cfg.crate_name == pkg.name)
+                    .map(|cfg| format!("{} → {}{}", cfg.repo_label(), cfg.workflow, cfg.environment_label()))
+                    .collect();
+                if replaced.is_empty() {
+                    pkg.name.clone()
+                } else {
+                    format!("{}  {}", pkg.name, format!("(replaces {})", replaced.join(", ")).dimmed())
+                }
+            })
+            .collect();
+        let chosen = prompt.multi_select("Crates to configure", &names)?;
+        let (keep, drop): (Vec<_>, Vec<_>) = to_configure
+            .into_iter()
+            .enumerate()
+            .partition(|(i, _)| chosen.contains(i));
+        to_configure = keep.into_iter().map(|(_, pkg)| pkg).collect();
+        deselected = drop.into_iter().map(|(_, pkg)| pkg).collect();
+        for pkg in &deselected {
+            if let Some(p) = report.package_mut(&pkg.name) {
+                p.result = Some("skipped-by-user".to_string());
+            }
+        }
+        to_prune.retain(|cfg| !deselected.iter().any(|pkg| pkg.name == cfg.crate_name));
+        if to_configure.is_empty() && to_prune.is_empty() {
+            say!("{}", "No crates selected.".yellow());
+            return Ok(Outcome::Aborted);
+        }
+    }
 
-    if !args.dry_run && !ask_yes_no("Proceed with trusted publishing setup?") {
-        println!("{}", "Aborted.".yellow());
-        return Ok(());
+    if !dry_run && !prompt.confirm("Proceed with trusted publishing setup?")? {
+        say!("{}", "Aborted.".yellow());
+        for pkg in &to_configure {
+            if let Some(p) = report.package_mut(&pkg.name) {
+                p.result = Some("skipped".to_string());
+            }
+        }
+        return Ok(Outcome::Aborted);
     }
 
-    let pb = ProgressBar::new(to_configure.len() as u64);
-    pb.set_style(
-        ProgressStyle::default_bar()
-            .template("{msg} [{bar:30}] {pos}/{len}")
-            .unwrap()
-            .progress_chars("=> "),
-    );
+    let pb = progress_bar((to_configure.len() + to_prune.len()) as u64);
 
     let mut errors = Vec::new();
+    // Crates crates.io turned away though listing their configurations worked
+    let mut refused = Vec::new();
+    // Crates with the desired configuration in place, whose stray ones can go
+    let mut replaced: HashSet<&str> = plan.correct.iter().map(|cfg| cfg.crate_name.as_str()).collect();
     for pkg in &to_configure {
         pb.set_message(format!("Configuring {}", pkg.name));
 
-        if !args.dry_run {
-            let config = GithubConfigRequest {
-                github_config: GithubConfigInner {
-                    crate_name: pkg.name.clone(),
-                    repository_owner: owner.clone(),
-                    repository_name: repo.clone(),
-                    workflow_filename: workflow.clone(),
-                },
+        if !dry_run {
+            let d = desired.for_package(pkg);
+            let result = match create_trustpub_config(&client, &token, &pkg.name, &d).await {
+                Ok(()) => {
+                    cache.configured.insert(cache_key(d.owner, d.repo, &pkg.name));
+                    replaced.insert(&pkg.name);
+                    "configured"
+                }
+                Err(e) if e.is::<NotOwned>() => {
+                    refused.push(pkg.name.clone());
+                    "not-owned"
+                }
+                Err(e) => {
+                    report.add_error(&pkg.name, &e.to_string());
+                    errors.push((pkg.name.clone(), e.to_string()));
+                    "failed"
+                }
             };
+            if let Some(p) = report.package_mut(&pkg.name) {
+                p.configured = result == "configured";
+                p.result = Some(result.to_string());
+            }
+        } else {
+            // In dry-run, don't cache but still count as "would configure"
+            replaced.insert(&pkg.name);
+            if let Some(p) = report.package_mut(&pkg.name) {
+                p.result = Some("would-configure".to_string());
+            }
+        }
 
-            if let Err(e) = create_trustpub_github_config(&client, &token, &config).await {
-                errors.push((pkg.name.clone(), e.to_string()));
+        pb.inc(1);
+    }
+
+    // A stray configuration may be the only one that works; it only goes
+    // once its replacement is in place, so no crate (least of all a locked
+    // one) is left without a configuration
+    let (to_prune, kept): (Vec<&TrustpubConfig>, Vec<&TrustpubConfig>) = to_prune
+        .into_iter()
+        .partition(|cfg| replaced.contains(cfg.crate_name.as_str()));
+    for cfg in &kept {
+        report.set_config_state(cfg, "kept");
+        pb.inc(1);
+    }
+
+    let mut prune_errors = Vec::new();
+    for cfg in &to_prune {
+        pb.set_message(format!("Pruning {}", cfg.crate_name));
+
+        if !dry_run {
+            if let Err(e) = delete_trustpub_config(&client, &token, cfg).await {
+                report.add_error(&cfg.crate_name, &e.to_string());
+                report.set_config_state(cfg, "failed");
+                prune_errors.push((cfg.crate_name.clone(), e.to_string()));
             } else {
-                cache.configured.insert(cache_key(&owner, &repo, &pkg.name));
+                report.set_config_state(cfg, "pruned");
+                let wanted = packages
+                    .iter()
+                    .find(|pkg| pkg.name == cfg.crate_name)
+                    .map(|pkg| desired.for_package(pkg))
+                    .is_some_and(|d| d.owner == cfg.owner && d.repo == cfg.repo);
+                if !wanted {
+                    cache.configured.remove(&cache_key(&cfg.owner, &cfg.repo, &cfg.crate_name));
+                }
             }
-
-            tokio::time::sleep(Duration::from_millis(1100)).await;
         } else {
-            // In dry-run, don't cache but still count as "would configure"
+            report.set_config_state(cfg, "would-prune");
         }
 
         pb.inc(1);
     }
     pb.finish_and_clear();
 
-    if !args.dry_run {
+    if !dry_run {
         if let Err(e) = save_cache(&cache) {
             eprintln!("{} could not save cache: {}", "⚠️  Warning:".yellow(), e);
         }
     }
 
+    owners::print_refused(&refused, client.label(), report);
+    let success_count = to_configure.len() - errors.len() - refused.len();
+    not_owned.extend(refused);
+
     if !errors.is_empty() {
-        println!("\n{}", "❌ Errors configuring trusted publishing:".red());
+        say!("\n{}", "❌ Errors configuring trusted publishing:".red());
         for (name, err) in &errors {
-            println!("   {} {} {}", name.cyan(), "✗".red(), err.dimmed());
+            say!("   {} {} {}", name.cyan(), "✗".red(), err.dimmed());
+        }
+    }
+    if !prune_errors.is_empty() {
+        say!("\n{}", "❌ Errors pruning stray configurations:".red());
+        for (name, err) in &prune_errors {
+            say!("   {} {} {}", name.cyan(), "✗".red(), err.dimmed());
+        }
+    }
+    if !kept.is_empty() {
+        say!("\n{}", "⚠️  Kept: replacement failed".yellow());
+        for cfg in &kept {
+            say!(
+                "   {} {} {} {}{}",
+                "•".dimmed(),
+                cfg.crate_name.cyan(),
+                cfg.repo_label().green(),
+                cfg.workflow.yellow(),
+                cfg.environment_label().yellow()
+            );
         }
+        say!(
+            "   {}",
+            "(not deleted, since the crate would be left without a working configuration)".dimmed()
+        );
     }
 
-    let success_count = to_configure.len() - errors.len();
-    if args.dry_run {
-        println!(
+    if dry_run {
+        say!(
             "\n{} Would configure trusted publishing for {} crate{}.",
             "(dry run)".dimmed(),
             to_configure.len().to_string().bright_white(),
             if to_configure.len() == 1 { "" } else { "s" }
         );
-    } else if errors.is_empty() {
-        println!(
-            "\n{} Configured trusted publishing for {} crate{}.",
-            "✅".green(),
-            success_count.to_string().bright_white().bold(),
-            if to_configure.len() == 1 { "" } else { "s" }
-        );
+        if !to_prune.is_empty() {
+            say!(
+                "{} Would delete {} stray configuration{}.",
+                "(dry run)".dimmed(),
+                to_prune.len().to_string().bright_white(),
+                if to_prune.len() == 1 { "" } else { "s" }
+            );
+        }
     } else {
-        println!(
-            "\n{} Configured trusted publishing for {}/{} crate{}.",
-            "⚠️".yellow(),
-            success_count.to_string().green(),
-            to_configure.len(),
-            if to_configure.len() == 1 { "" } else { "s" }
-        );
+        if success_count == to_configure.len() {
+            say!(
+                "\n{} Configured trusted publishing for {} crate{}.",
+                "✅".green(),
+                success_count.to_string().bright_white().bold(),
+                if to_configure.len() == 1 { "" } else { "s" }
+            );
+        } else {
+            say!(
+                "\n{} Configured trusted publishing for {}/{} crate{}.",
+                "⚠️".yellow(),
+                success_count.to_string().green(),
+                to_configure.len(),
+                if to_configure.len() == 1 { "" } else { "s" }
+            );
+        }
+        if !to_prune.is_empty() {
+            say!(
+                "{} Deleted {}/{} stray configuration{}.",
+                if prune_errors.is_empty() { "✅".green().to_string() } else { "⚠️".yellow().to_string() },
+                (to_prune.len() - prune_errors.len()).to_string().bright_white().bold(),
+                to_prune.len(),
+                if to_prune.len() == 1 { "" } else { "s" }
+            );
+        }
+    }
+    if !deselected.is_empty() {
+        let names: Vec<&str> = deselected.iter().map(|pkg| pkg.name.as_str()).collect();
+        say!("{} {}", "⏭️  Skipped by you:".dimmed(), names.join(", ").dimmed());
+    }
+    if !not_owned.is_empty() {
+        let mut names: Vec<&str> = not_owned.iter().map(String::as_str).collect();
+        names.sort();
+        say!("{} {}", "⏭️  Not yours:".dimmed(), names.join(", ").dimmed());
+    }
+
+    let outcome = if errors.is_empty() && prune_errors.is_empty() {
+        Outcome::Success
+    } else {
+        Outcome::PartialFailure
+    };
+    if lock {
+        let locked = lock::lock_published(&client, &token, &packages, prompt, report, dry_run).await?;
+        return Ok(outcome.and(locked));
+    }
+    Ok(outcome)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(line: &str) -> Vec<String> {
+        cli_args(line.split_whitespace().map(str::to_string).collect())
+    }
+
+    #[test]
+    fn bare_owner_and_repo_mean_setup() {
+        assert_eq!(args("facet-rs facet -w release.yml"), ["setup", "facet-rs", "facet", "-w", "release.yml"]);
+        assert_eq!(args("-n"), ["setup", "-n"]);
+        assert_eq!(args(""), ["setup"]);
+    }
+
+    #[test]
+    fn subcommands_and_builtins_are_left_alone() {
+        assert_eq!(args("status --check"), ["status", "--check"]);
+        assert_eq!(args("workflow init"), ["workflow", "init"]);
+        assert_eq!(args("--help"), ["--help"]);
+        assert_eq!(args("--version"), ["--version"]);
+        assert_eq!(args("lock -p facet"), ["lock", "-p", "facet"]);
+        assert_eq!(args("status facet-rs"), ["status", "facet-rs"]);
+    }
+
+    #[test]
+    fn owner_named_like_a_subcommand_means_setup() {
+        assert_eq!(args("lock facet"), ["setup", "lock", "facet"]);
+        assert_eq!(args("owners facet -w release.yml"), ["setup", "owners", "facet", "-w", "release.yml"]);
+        assert_eq!(args("workflow facet"), ["setup", "workflow", "facet"]);
+        assert_eq!(args("setup status facet"), ["setup", "status", "facet"]);
     }
-    Ok(())
 }
@@ -0,0 +1,131 @@
+//! `tp workflow migrate`: moves an existing workflow that publishes with the
+//! `CARGO_REGISTRY_TOKEN` secret to trusted publishing, editing the file in
+//! place. The rewrite only touches the lines it has to (see
+//! [`Workflow::migrate`]) and is shown as a diff before anything is written.
+
+use color_eyre::eyre::{Result, eyre};
+use owo_colors::OwoColorize;
+
+use crate::diff;
+use crate::exit::Outcome;
+use crate::prompt::Prompt;
+use crate::report::{CheckReport, Report};
+use crate::workflow::{self, Workflow, workflows_dir};
+use crate::{list_workflow_files, select_workflow};
+
+pub fn migrate(file: Option<&str>, prompt: &Prompt, report: &mut Report, dry_run: bool) -> Result<Outcome> {
+    report.dry_run = dry_run;
+
+    let file = match file {
+        Some(file) => file.to_string(),
+        // A reusable workflow holds the steps to rewrite, so it is a
+        // candidate too
+        None => select_workflow(&list_workflow_files()?, prompt)?,
+    };
+    say!("{} {}", "⚙️  Workflow:".cyan(), file.yellow());
+    report.workflow = Some(file.clone());
+
+    let path = workflows_dir().join(&file);
+    let original =
+        std::fs::read_to_string(&path).map_err(|e| eyre!("Could not read {}: {}", path.display(), e))?;
+    let current = Workflow::load(&file)?;
+    let migration = current.migrate()?;
+
+    if migration.changes.is_empty() {
+        print_manual(&migration.manual);
+        if !migration.manual.is_empty() {
+            return Ok(Outcome::NothingToDo);
+        }
+        say!(
+            "\n{} No publishing job of {} reads secrets.CARGO_REGISTRY_TOKEN; nothing to migrate.",
+            "✓".green(),
+            file
+        );
+        return Ok(Outcome::Success);
+    }
+
+    say!("\n{}", "🔧 Changes:".cyan());
+    for change in &migration.changes {
+        say!("   {} {}", "•".dimmed(), change);
+    }
+    say!();
+    let display_path = path.display().to_string();
+    diff::print(&diff::unified(&display_path, current.lines(), migration.workflow.lines()));
+    print_manual(&migration.manual);
+
+    if dry_run {
+        say!("\n{} Would write {}", "(dry run)".dimmed(), display_path.yellow());
+        return Ok(Outcome::Success);
+    }
+
+    say!();
+    if !prompt.confirm(&format!("Write these changes to {}?", display_path))? {
+        say!("{}", "Aborted.".yellow());
+        return Ok(Outcome::Aborted);
+    }
+
+    std::fs::write(&path, render(&original, migration.workflow.lines()))?;
+    say!("\n{} Wrote {}", "✅".green(), display_path.yellow());
+
+    say!();
+    let findings = migration.workflow.lint();
+    workflow::print_findings(&file, &findings);
+    report.workflow_checks.extend(findings.into_iter().map(|f| CheckReport {
+        workflow: file.clone(),
+        check: f.check.to_string(),
+        passed: f.passed,
+        message: f.message,
+    }));
+
+    say!(
+        "\n{}",
+        format!(
+            "Run `tp setup -w {}` to configure trusted publishing for it. Once a release went through, delete the CARGO_REGISTRY_TOKEN secret (and `tp lock` the crates).",
+            file
+        )
+        .dimmed()
+    );
+
+    Ok(Outcome::Success)
+}
+
+/// The file contents for `lines`, keeping the line endings of `original`
+/// and its final newline if it had one.
+fn render(original: &str, lines: &[String]) -> String {
+    let newline = if original.contains("\r\n") { "\r\n" } else { "\n" };
+    let mut contents = lines.join(newline);
+    if original.ends_with('\n') {
+        contents.push_str(newline);
+    }
+    contents
+}
+
+fn print_manual(manual: &[String]) {
+    if manual.is_empty() {
+        return;
+    }
+    say!("\n{}", "⚠️  Left to do by hand:".yellow());
+    for item in manual {
+        say!("   {} {}", "•".dimmed(), item);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_crlf_line_endings() {
+        let original = "on: push\r\njobs:\r\n";
+        let mut new: Vec<String> = original.lines().map(str::to_string).collect();
+        new.push("  # added".to_string());
+        assert_eq!(render(original, &new), "on: push\r\njobs:\r\n  # added\r\n");
+    }
+
+    #[test]
+    fn keeps_a_missing_final_newline_missing() {
+        let new = ["on: push".to_string(), "jobs:".to_string()];
+        assert_eq!(render("on: push\njobs:", &new), "on: push\njobs:");
+        assert_eq!(render("on: push\njobs:\n", &new), "on: push\njobs:\n");
+    }
+}
@@ -0,0 +1,67 @@
+//! `[package.metadata.tp]` and `[workspace.metadata.tp]`: settings kept in
+//! Cargo.toml, so one run can set up a workspace whose crates publish from
+//! different workflows, environments or repositories.
+//!
+//! Precedence, highest first: the crate's own table, command-line flags, the
+//! workspace table, config files, then whatever `tp` detects or asks for.
+
+use facet::Facet;
+
+/// A `metadata` table as it appears in `cargo metadata` output, either a
+/// package's or (top-level) the workspace's.
+#[derive(Facet, Debug, Clone, Default)]
+pub struct Metadata {
+    pub tp: Option<Overrides>,
+}
+
+#[derive(Facet, Debug, Clone, Default)]
+pub struct Overrides {
+    /// Leave the crate out of every run (package table only)
+    pub skip: Option<bool>,
+    /// Workflow filename, or CI file path for GitLab
+    pub workflow: Option<String>,
+    /// Deployment environment the publishing job runs in
+    pub environment: Option<String>,
+    /// "owner/repo", or "namespace/project" on GitLab
+    pub repository: Option<String>,
+}
+
+impl Overrides {
+    pub fn skip(&self) -> bool {
+        self.skip == Some(true)
+    }
+
+    /// The `repository` override split into owner and repo. GitLab
+    /// namespaces may contain slashes, so only the last one separates the
+    /// project.
+    pub fn repository(&self) -> Option<(&str, &str)> {
+        self.repository.as_deref().and_then(split_repository)
+    }
+
+    /// These settings, with what they leave unset taken from `lower`. As
+    /// with a crate's table, setting the workflow also drops `lower`'s
+    /// environment, which belongs to `lower`'s workflow.
+    pub fn or(&self, lower: &Overrides) -> Overrides {
+        let (workflow, environment) = match &self.workflow {
+            Some(workflow) => (Some(workflow.clone()), self.environment.clone()),
+            None => (
+                lower.workflow.clone(),
+                self.environment.clone().or_else(|| lower.environment.clone()),
+            ),
+        };
+        Overrides {
+            skip: self.skip.or(lower.skip),
+            workflow,
+            environment,
+            repository: self.repository.clone().or_else(|| lower.repository.clone()),
+        }
+    }
+}
+
+fn split_repository(repository: &str) -> Option<(&str, &str)> {
+    let (owner, repo) = repository.rsplit_once('/')?;
+    if owner.is_empty() || repo.is_empty() {
+        return None;
+    }
+    Some((owner, repo))
+}
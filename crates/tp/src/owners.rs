@@ -0,0 +1,458 @@
+//! Who owns the workspace's crates. A taken name isn't necessarily ours: a
+//! stranger (or a typosquatter) may have claimed it first, and crates.io
+//! would turn every request about it away with a bare 403.
+//!
+//! `tp owners` also brings every crate's owners in line with one list, so a
+//! skeleton published by whoever ran `tp` ends up with the same owners as
+//! the rest of the workspace.
+
+use std::collections::HashSet;
+
+use color_eyre::eyre::{Result, WrapErr};
+use futures::{StreamExt, stream};
+use owo_colors::OwoColorize;
+
+use crate::api::{ApiClient, Owner, add_owners, crate_owners, list_trustpub_configs, remove_owners};
+use crate::exit::Outcome;
+use crate::prompt::Prompt;
+use crate::registry::Registry;
+use crate::report::{PackageReport, Report};
+use crate::select::PackageArgs;
+use crate::{Forge, Package, check_published, load_workspace, progress_bar, resolve_token};
+
+/// The owners every crate should have. Empty means just show them.
+pub struct DesiredOwners {
+    /// crates.io logins and teams like `github:org:team`
+    pub owners: Vec<String>,
+    /// Also remove owners that aren't in `owners`
+    pub remove_extra: bool,
+}
+
+/// One crate's owners compared to the desired ones.
+struct OwnersDiff<'a> {
+    pkg: &'a Package,
+    owners: Vec<Owner>,
+    missing: Vec<String>,
+    extra: Vec<String>,
+}
+
+/// How a crate relates to the account the token acts as.
+pub enum Ownership {
+    /// Listed as an owner in person
+    Mine,
+    /// Not listed in person, but owned by these teams, which the account may
+    /// belong to (crates.io doesn't say who is in a team)
+    ViaTeam(Vec<String>),
+    /// Owned by these users, none of them the account
+    NotOwned(Vec<String>),
+}
+
+impl Ownership {
+    /// GitHub logins are case-insensitive, and crates.io keeps whatever case
+    /// GitHub reported.
+    pub fn of(owners: &[Owner], login: &str) -> Self {
+        if owners.iter().any(|o| !o.is_team() && o.login.eq_ignore_ascii_case(login)) {
+            return Ownership::Mine;
+        }
+        let (teams, users): (Vec<&Owner>, Vec<&Owner>) = owners.iter().partition(|o| o.is_team());
+        if teams.is_empty() {
+            Ownership::NotOwned(users.into_iter().map(|o| o.login.clone()).collect())
+        } else {
+            Ownership::ViaTeam(teams.into_iter().map(|o| o.login.clone()).collect())
+        }
+    }
+}
+
+/// Looks up the owners of every published crate, prints them grouped by how
+/// `login` owns them, and returns the names of the crates `login` doesn't
+/// own at all, which are marked "not-owned" in the report.
+///
+/// Without the login (crates.io never gives it for an API token), the
+/// registry is asked for each crate's configurations instead: it refuses the
+/// token about crates its account can't manage. The account is then the one
+/// user owning every crate it can, when there is just one.
+pub async fn check(
+    client: &ApiClient,
+    token: &str,
+    login: Option<&str>,
+    crates: &[&Package],
+    report: &mut Report,
+) -> Result<HashSet<String>> {
+    if crates.is_empty() {
+        return Ok(HashSet::new());
+    }
+
+    let refused = match login {
+        Some(_) => Vec::new(),
+        None => {
+            let packages: Vec<Package> = crates.iter().map(|&pkg| pkg.clone()).collect();
+            list_trustpub_configs(client, token, &packages, &[Forge::Github]).await?.not_owned
+        }
+    };
+
+    let mut mine = Vec::new();
+    let mut via_team = Vec::new();
+    let mut not_owned = Vec::new();
+    // Users owning every crate the token may manage
+    let mut candidates: Option<Vec<String>> = None;
+    for (pkg, owners) in lookup(client, crates).await? {
+        let ownership = match login {
+            Some(login) => Ownership::of(&owners, login),
+            None if refused.contains(&pkg.name) => {
+                Ownership::NotOwned(owners.iter().map(|o| o.login.clone()).collect())
+            }
+            None => {
+                let users: Vec<String> = owners.iter().filter(|o| !o.is_team()).map(|o| o.login.clone()).collect();
+                candidates = Some(match candidates {
+                    None => users,
+                    Some(c) => c.into_iter().filter(|l| users.iter().any(|u| u.eq_ignore_ascii_case(l))).collect(),
+                });
+                Ownership::Mine
+            }
+        };
+        match ownership {
+            Ownership::Mine => mine.push(pkg.name.as_str()),
+            Ownership::ViaTeam(teams) => via_team.push((pkg.name.as_str(), teams)),
+            Ownership::NotOwned(users) => not_owned.push((pkg.name.as_str(), users)),
+        }
+    }
+    mine.sort();
+    via_team.sort();
+    not_owned.sort();
+
+    let inferred = match candidates {
+        Some(mut c) if c.len() == 1 => c.pop(),
+        _ => None,
+    };
+    let account = login.or(inferred.as_deref()).unwrap_or("the token's account");
+
+    say!("\n{}", "👥 Ownership:".cyan());
+    if let Some(login) = &inferred {
+        say!(
+            "   {} {} {}",
+            "👤".cyan(),
+            login.green(),
+            "(the one owner of every crate the token may manage)".dimmed()
+        );
+        report.user = Some(login.clone());
+    }
+    if !mine.is_empty() {
+        say!("   {} {} {}", "✓".green(), "yours:".dimmed(), mine.join(", "));
+    }
+    for (name, teams) in &via_team {
+        say!(
+            "   {} {} {} {}",
+            "~".yellow(),
+            name.bright_white(),
+            "via team".dimmed(),
+            teams.join(", ").yellow()
+        );
+    }
+    if !via_team.is_empty() {
+        say!(
+            "     {}",
+            format!("(kept; this only works if {} is in one of those teams)", account).dimmed()
+        );
+    }
+    for (name, users) in &not_owned {
+        say!(
+            "   {} {} {} {}",
+            "✗".red(),
+            name.bright_white(),
+            "owned by".dimmed(),
+            users.join(", ").red()
+        );
+        if let Some(p) = report.package_mut(name) {
+            p.result = Some("not-owned".to_string());
+        }
+    }
+    if !not_owned.is_empty() {
+        say!(
+            "     {}",
+            format!(
+                "(left out: {} doesn't own {}; if the name was taken by someone else, rename the crate or ask an owner to add you)",
+                account,
+                if not_owned.len() == 1 { "it" } else { "them" }
+            )
+            .dimmed()
+        );
+    }
+
+    Ok(not_owned.into_iter().map(|(name, _)| name.to_string()).collect())
+}
+
+/// Prints the crates crates.io refused the token about, which is how crates
+/// the account doesn't own show when it can't say who the account is, and
+/// marks them "not-owned" in the report.
+pub fn print_refused(names: &[String], registry: &str, report: &mut Report) {
+    if names.is_empty() {
+        return;
+    }
+
+    say!("\n{}", "👥 Ownership:".cyan());
+    for name in names {
+        say!(
+            "   {} {} {}",
+            "✗".red(),
+            name.bright_white(),
+            format!("refused by {}", registry).dimmed()
+        );
+        if let Some(p) = report.package_mut(name) {
+            p.result = Some("not-owned".to_string());
+        }
+    }
+    say!(
+        "     {}",
+        format!(
+            "(left out: the token's account doesn't own {}, or the token's crate scopes leave {} out; run `tp owners` to see who does)",
+            if names.len() == 1 { "it" } else { "them" },
+            if names.len() == 1 { "it" } else { "them" }
+        )
+        .dimmed()
+    );
+}
+
+/// The owners of each crate, looked up in parallel.
+async fn lookup<'a>(client: &ApiClient, crates: &[&'a Package]) -> Result<Vec<(&'a Package, Vec<Owner>)>> {
+    let pb = progress_bar(crates.len() as u64);
+    pb.set_message("Checking owners");
+    let lookups: Vec<_> = stream::iter(crates.iter().map(|&pkg| {
+        let pb = &pb;
+        async move {
+            let owners = crate_owners(client, &pkg.name).await;
+            pb.inc(1);
+            (pkg, owners)
+        }
+    }))
+    .buffer_unordered(20)
+    .collect()
+    .await;
+    pb.finish_and_clear();
+
+    let mut results = Vec::with_capacity(lookups.len());
+    for (pkg, owners) in lookups {
+        let owners = owners.wrap_err_with(|| format!("Could not check who owns {}", pkg.name))?;
+        results.push((pkg, owners));
+    }
+    results.sort_by(|a, b| a.0.name.cmp(&b.0.name));
+    Ok(results)
+}
+
+/// `tp owners`: shows every published crate's owners and, given a desired
+/// list, invites the missing users and adds the missing teams. Owners not on
+/// the list are only removed with `remove_extra`, and not while an invited
+/// user has yet to accept.
+pub async fn owners(
+    desired: &DesiredOwners,
+    packages: &PackageArgs,
+    token_env: Option<&str>,
+    registry: &Registry,
+    prompt: &Prompt,
+    report: &mut Report,
+    dry_run: bool,
+) -> Result<Outcome> {
+    report.dry_run = dry_run;
+
+    let token = resolve_token(token_env, registry, report)?;
+
+    let packages = load_workspace(registry, packages, report)?.packages;
+    report.packages.extend(packages.iter().map(PackageReport::new));
+    if packages.is_empty() {
+        say!("{}", "No publishable crates found.".yellow());
+        return Ok(Outcome::NothingToDo);
+    }
+
+    let client = ApiClient::new(registry).await?;
+
+    // A crate that was never published has no owners yet
+    let published = check_published(&client, &packages).await?;
+    let (published, unpublished): (Vec<&Package>, Vec<&Package>) = packages
+        .iter()
+        .partition(|pkg| published.get(&pkg.name) == Some(&true));
+    for pkg in &unpublished {
+        if let Some(p) = report.package_mut(&pkg.name) {
+            p.published = Some(false);
+            p.result = Some("skipped".to_string());
+        }
+    }
+
+    let diffs: Vec<OwnersDiff> = lookup(&client, &published)
+        .await?
+        .into_iter()
+        .map(|(pkg, owners)| {
+            let has = |login: &str| owners.iter().any(|o| o.login.eq_ignore_ascii_case(login));
+            let wants = |login: &str| desired.owners.iter().any(|d| d.eq_ignore_ascii_case(login));
+            let missing = desired.owners.iter().filter(|d| !has(d)).cloned().collect();
+            let extra = if desired.owners.is_empty() {
+                Vec::new()
+            } else {
+                owners.iter().filter(|o| !wants(&o.login)).map(|o| o.login.clone()).collect()
+            };
+            OwnersDiff {
+                pkg,
+                owners,
+                missing,
+                extra,
+            }
+        })
+        .collect();
+
+    say!("{}", "👥 Owners:".cyan());
+    for diff in &diffs {
+        let owners: Vec<String> = diff
+            .owners
+            .iter()
+            .map(|o| {
+                let label = if o.is_team() { o.login.yellow().to_string() } else { o.login.clone() };
+                match diff.extra.contains(&o.login) {
+                    true if desired.remove_extra => format!("{} {}", label, "(remove)".red()),
+                    true => format!("{} {}", label, "(extra)".dimmed()),
+                    false => label,
+                }
+            })
+            .collect();
+        say!("   {} {}", diff.pkg.name.bright_white(), owners.join(", "));
+        if !diff.missing.is_empty() {
+            say!("     {} {}", "+".green(), diff.missing.join(", ").green());
+        }
+        if let Some(p) = report.package_mut(&diff.pkg.name) {
+            p.published = Some(true);
+        }
+    }
+    if !unpublished.is_empty() {
+        let names: Vec<&str> = unpublished.iter().map(|pkg| pkg.name.as_str()).collect();
+        say!("   {} {}", "⏭️  Not published yet:".dimmed(), names.join(", ").dimmed());
+    }
+
+    if desired.owners.is_empty() {
+        say!(
+            "\n{}",
+            "Pass --crate-owner (or set crate-owners in tp.toml) to give every crate the same owners.".dimmed()
+        );
+        return Ok(Outcome::Success);
+    }
+
+    let to_change: Vec<&OwnersDiff> = diffs
+        .iter()
+        .filter(|d| !d.missing.is_empty() || (desired.remove_extra && !d.extra.is_empty()))
+        .collect();
+    for diff in diffs.iter().filter(|d| !to_change.iter().any(|c| c.pkg.name == d.pkg.name)) {
+        if let Some(p) = report.package_mut(&diff.pkg.name) {
+            p.result = Some("owners-match".to_string());
+        }
+    }
+    if to_change.is_empty() {
+        say!("\n{} Every crate has the desired owners.", "✓".green());
+        if diffs.iter().any(|d| !d.extra.is_empty()) {
+            say!("   {}", "(extra owners left alone; pass --remove-extra to remove them)".dimmed());
+        }
+        return Ok(Outcome::Success);
+    }
+
+    if dry_run {
+        for diff in &to_change {
+            if let Some(p) = report.package_mut(&diff.pkg.name) {
+                p.result = Some("would-change-owners".to_string());
+            }
+        }
+        say!(
+            "\n{} Would change the owners of {} crate{}.",
+            "(dry run)".dimmed(),
+            to_change.len().to_string().bright_white(),
+            if to_change.len() == 1 { "" } else { "s" }
+        );
+        return Ok(Outcome::Success);
+    }
+
+    say!();
+    if !prompt.confirm("Update these owners?")? {
+        say!("{}", "Aborted.".yellow());
+        return Ok(Outcome::Aborted);
+    }
+
+    let pb = progress_bar(to_change.len() as u64);
+    let mut errors = Vec::new();
+    let mut messages = Vec::new();
+    let mut deferred = Vec::new();
+    for diff in &to_change {
+        let name = &diff.pkg.name;
+        pb.set_message(format!("Updating {}", name));
+
+        let mut result = Ok(());
+        if !diff.missing.is_empty() {
+            match add_owners(&client, &token, name, &diff.missing).await {
+                Ok(msg) if !msg.is_empty() => messages.push((name, msg)),
+                Ok(_) => {}
+                Err(e) => result = Err(e),
+            }
+        }
+        // A missing user is only invited, and stays missing until they
+        // accept; removing owners meanwhile could leave the crate with none
+        // of the desired ones, so that waits for a later run
+        let invited = diff.missing.iter().any(|login| !login.contains(':'));
+        if result.is_ok() && desired.remove_extra && !diff.extra.is_empty() {
+            if invited {
+                deferred.push(name);
+            } else {
+                result = remove_owners(&client, &token, name, &diff.extra).await.map(|_| ());
+            }
+        }
+
+        match result {
+            Ok(()) => {
+                let changed = if deferred.contains(&name) { "removal-deferred" } else { "owners-changed" };
+                if let Some(p) = report.package_mut(name) {
+                    p.result = Some(changed.to_string());
+                }
+            }
+            Err(e) => {
+                if let Some(p) = report.package_mut(name) {
+                    p.result = Some("failed".to_string());
+                }
+                report.add_error(name, &e.to_string());
+                errors.push((name, e.to_string()));
+            }
+        }
+        pb.inc(1);
+    }
+    pb.finish_and_clear();
+
+    for (name, msg) in &messages {
+        say!("   {} {}", name.cyan(), msg.dimmed());
+    }
+    if !deferred.is_empty() {
+        say!(
+            "\n{} {}",
+            "⏳ Removals deferred for:".yellow(),
+            deferred.iter().map(|name| name.as_str()).collect::<Vec<_>>().join(", ")
+        );
+        say!(
+            "   {}",
+            "(extra owners stay until the invited users accept; run again with --remove-extra then)".dimmed()
+        );
+    }
+    if !errors.is_empty() {
+        say!("\n{}", "❌ Errors updating owners:".red());
+        for (name, err) in &errors {
+            say!("   {} {} {}", name.cyan(), "✗".red(), err.dimmed());
+        }
+    }
+
+    say!(
+        "\n{} Updated the owners of {}/{} crate{}.",
+        if errors.is_empty() { "✅".green().to_string() } else { "⚠️".yellow().to_string() },
+        (to_change.len() - errors.len()).to_string().bright_white().bold(),
+        to_change.len(),
+        if to_change.len() == 1 { "" } else { "s" }
+    );
+    say!(
+        "   {}",
+        "Invited users only become owners once they accept; until then they still show up as missing.".dimmed()
+    );
+
+    if errors.is_empty() {
+        Ok(Outcome::Success)
+    } else {
+        Ok(Outcome::PartialFailure)
+    }
+}
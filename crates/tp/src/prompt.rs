@@ -0,0 +1,96 @@
+//! Every question `tp` asks goes through here, so that runs without a TTY
+//! (CI, piped output) are answered by flags or fail with an error naming the
+//! flag to pass, instead of hanging or quietly answering "no".
+
+use std::io::IsTerminal;
+
+use color_eyre::eyre::{Result, bail};
+use dialoguer::{Confirm, MultiSelect, Select, theme::ColorfulTheme};
+use facet::Facet;
+use figue as args;
+use owo_colors::OwoColorize;
+
+#[derive(Facet, Debug, Default)]
+pub struct PromptArgs {
+    /// Answer yes to every confirmation prompt
+    #[facet(args::named, args::short = 'y', default)]
+    pub yes: bool,
+
+    /// Never prompt, fail instead when a question needs an answer (implied when stdin is not a TTY)
+    #[facet(args::named, default)]
+    pub no_input: bool,
+}
+
+pub struct Prompt {
+    yes: bool,
+    interactive: bool,
+}
+
+impl Prompt {
+    pub fn new(args: &PromptArgs) -> Self {
+        Prompt {
+            yes: args.yes,
+            interactive: !args.no_input && std::io::stdin().is_terminal(),
+        }
+    }
+
+    /// Whether `confirm` can produce an answer at all.
+    pub fn can_confirm(&self) -> bool {
+        self.yes || self.interactive
+    }
+
+    /// Whether `select` can produce an answer at all.
+    pub fn can_select(&self) -> bool {
+        self.interactive
+    }
+
+    pub fn confirm(&self, question: &str) -> Result<bool> {
+        if self.yes {
+            say!("{} {}", question, "yes (--yes)".dimmed());
+            return Ok(true);
+        }
+        if !self.interactive {
+            bail!("\"{}\" needs an answer but there is no terminal to ask on. Pass --yes to proceed.", question);
+        }
+
+        Ok(Confirm::with_theme(&ColorfulTheme::default())
+            .with_prompt(question)
+            .default(true)
+            .interact()?)
+    }
+
+    /// Picks one of `items`; `flag` is the option that makes the choice
+    /// without a terminal (e.g. "-w <file>").
+    pub fn select(&self, question: &str, items: &[String], flag: &str) -> Result<usize> {
+        if !self.interactive {
+            bail!(
+                "\"{}\" needs an answer but there is no terminal to ask on. Pass {} to choose one of: {}",
+                question,
+                flag,
+                items.join(", ")
+            );
+        }
+
+        Ok(Select::with_theme(&ColorfulTheme::default())
+            .with_prompt(question)
+            .items(items)
+            .default(0)
+            .interact()?)
+    }
+
+    /// Lets the user untick some of `items`, all ticked to begin with.
+    /// Unlike the other questions this one has an answer without a terminal
+    /// (or with `--yes`): keep everything.
+    pub fn multi_select(&self, question: &str, items: &[String]) -> Result<Vec<usize>> {
+        if self.yes || !self.interactive {
+            return Ok((0..items.len()).collect());
+        }
+
+        let defaults = vec![true; items.len()];
+        Ok(MultiSelect::with_theme(&ColorfulTheme::default())
+            .with_prompt(format!("{} (space to toggle, enter to confirm)", question))
+            .items(items)
+            .defaults(&defaults)
+            .interact()?)
+    }
+}
@@ -0,0 +1,246 @@
+//! Which registry `tp` talks to. crates.io unless told otherwise, so it can
+//! also target staging.crates.io, a self-hosted registry implementing the
+//! trusted publishing API, or a local stand-in for testing.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use color_eyre::eyre::{Result, bail, eyre};
+use facet::Facet;
+use figue as args;
+
+/// Name cargo gives crates.io in `publish = [...]` and `--registry`
+pub const CRATES_IO: &str = "crates-io";
+const CRATES_IO_API: &str = "https://crates.io";
+const CRATES_IO_INDEX: &str = "https://index.crates.io/";
+
+#[derive(Facet, Debug, Default)]
+pub struct RegistryArgs {
+    /// Registry to target, by its name in cargo's config (env: TP_REGISTRY; default: crates.io)
+    #[facet(args::named)]
+    pub registry: Option<String>,
+
+    /// Base URL of the registry's web API (env: TP_API_URL; default: read from the index's config.json)
+    #[facet(args::named)]
+    pub api_url: Option<String>,
+
+    /// URL of the registry's sparse index (env: TP_INDEX_URL; default: from cargo's config for --registry)
+    #[facet(args::named)]
+    pub index_url: Option<String>,
+}
+
+/// The registry a run targets.
+#[derive(Debug, Clone)]
+pub struct Registry {
+    /// Name in cargo's config; `None` for crates.io or a registry only known
+    /// by its URLs
+    pub name: Option<String>,
+    /// Web API base URL, without trailing slash. `None` until read from the
+    /// index's `config.json`.
+    pub api_url: Option<String>,
+    /// Sparse index URL, without the `sparse+` prefix and with a trailing slash
+    pub index_url: String,
+}
+
+/// The parts of a cargo config file (or credentials file, which has the
+/// same shape) that `tp` cares about.
+#[derive(Facet, Debug)]
+pub struct CargoConfig {
+    /// crates.io
+    pub registry: Option<RegistryConfig>,
+    pub registries: Option<HashMap<String, RegistryConfig>>,
+    /// Names for credential provider commands, usable wherever a provider is
+    #[facet(rename = "credential-alias")]
+    pub credential_alias: Option<HashMap<String, ProviderCommand>>,
+}
+
+#[derive(Facet, Debug)]
+pub struct RegistryConfig {
+    pub index: Option<String>,
+    pub token: Option<String>,
+    /// The one provider to use for this registry, instead of the global list
+    #[facet(rename = "credential-provider")]
+    pub credential_provider: Option<ProviderCommand>,
+    /// Providers for every registry without its own (`[registry]` only)
+    #[facet(rename = "global-credential-providers")]
+    pub global_credential_providers: Option<Vec<String>>,
+}
+
+/// A credential provider as cargo's config spells it: a command line split
+/// on whitespace, or a list of arguments for paths containing spaces.
+#[derive(Facet, Debug, Clone)]
+#[facet(untagged)]
+#[repr(u8)]
+pub enum ProviderCommand {
+    Line(String),
+    Args(Vec<String>),
+}
+
+impl ProviderCommand {
+    pub fn args(&self) -> Vec<String> {
+        match self {
+            ProviderCommand::Line(line) => line.split_whitespace().map(str::to_string).collect(),
+            ProviderCommand::Args(args) => args.clone(),
+        }
+    }
+}
+
+impl CargoConfig {
+    /// Reads a cargo config or credentials file; `None` if it doesn't exist.
+    pub fn load(path: &Path) -> Result<Option<Self>> {
+        let Ok(contents) = std::fs::read_to_string(path) else {
+            return Ok(None);
+        };
+        let config =
+            facet_toml::from_str(&contents).map_err(|e| eyre!("Could not parse {}: {}", path.display(), e))?;
+        Ok(Some(config))
+    }
+
+    /// The table of the given registry, `None` meaning crates.io.
+    pub fn registry(self, name: Option<&str>) -> Option<RegistryConfig> {
+        match name {
+            None => self.registry,
+            Some(name) => self.registries.and_then(|mut r| r.remove(name)),
+        }
+    }
+
+    /// Same as [`CargoConfig::registry`], by reference.
+    pub fn registry_ref(&self, name: Option<&str>) -> Option<&RegistryConfig> {
+        match name {
+            None => self.registry.as_ref(),
+            Some(name) => self.registries.as_ref()?.get(name),
+        }
+    }
+}
+
+impl Registry {
+    /// Resolves the target registry from `args`, then cargo's own
+    /// configuration for named registries. The `TP_*` environment variables
+    /// and config files have already been folded into `args` (see
+    /// [`Config::apply_registry`](crate::config::Config::apply_registry)).
+    pub fn resolve(args: &RegistryArgs) -> Result<Self> {
+        let name = args.registry.clone().filter(|name| name != CRATES_IO);
+        let api_url = args.api_url.clone();
+        let index_url = args.index_url.clone();
+
+        let index_url = match (index_url, &name) {
+            (Some(url), _) => url,
+            (None, Some(name)) => configured_index(name)?,
+            (None, None) if api_url.is_some() => {
+                bail!("--api-url needs --index-url (or TP_INDEX_URL) to go with it")
+            }
+            (None, None) => {
+                return Ok(Registry {
+                    name: None,
+                    api_url: Some(CRATES_IO_API.to_string()),
+                    index_url: CRATES_IO_INDEX.to_string(),
+                });
+            }
+        };
+
+        Ok(Registry {
+            name,
+            api_url: api_url.map(|url| url.trim_end_matches('/').to_string()),
+            index_url: normalize_index_url(&index_url)?,
+        })
+    }
+
+    pub fn is_crates_io(&self) -> bool {
+        self.name.is_none() && self.index_url == CRATES_IO_INDEX
+    }
+
+    /// How to refer to the registry in output: its name, or else the index host
+    pub fn label(&self) -> String {
+        match &self.name {
+            Some(name) => name.clone(),
+            None if self.is_crates_io() => "crates.io".to_string(),
+            None => self.index_host().to_string(),
+        }
+    }
+
+    /// Host of the sparse index. Cargo names its local copy of the index
+    /// `<host>-<hash>`, under `registry/index`.
+    pub fn index_host(&self) -> &str {
+        let rest = self.index_url.split_once("://").map_or(self.index_url.as_str(), |(_, rest)| rest);
+        let host = rest.split('/').next().unwrap_or(rest);
+        host.split(':').next().unwrap_or(host)
+    }
+}
+
+/// `my-registry` → `MY_REGISTRY`, as in `CARGO_REGISTRIES_MY_REGISTRY_TOKEN`
+pub fn env_key(name: &str) -> String {
+    name.to_uppercase().replace('-', "_")
+}
+
+pub fn env(var: &str) -> Option<String> {
+    std::env::var(var).ok().filter(|v| !v.is_empty())
+}
+
+/// The index of a registry named in cargo's configuration, checking
+/// `CARGO_REGISTRIES_<NAME>_INDEX` first, then the config files cargo would
+/// read from the current directory.
+fn configured_index(name: &str) -> Result<String> {
+    let var = format!("CARGO_REGISTRIES_{}_INDEX", env_key(name));
+    let index = match env(&var) {
+        Some(index) => index,
+        None => index_from_config_files(name)?.ok_or_else(|| {
+            eyre!(
+                "Registry `{}` is not defined in cargo's config; add [registries.{}] with its index, or pass --index-url",
+                name,
+                name
+            )
+        })?,
+    };
+
+    // Without the prefix cargo treats the index as a git repository.
+    if !index.starts_with("sparse+") {
+        bail!("Registry `{}` uses a git index ({}); only sparse indexes are supported", name, index);
+    }
+    Ok(index)
+}
+
+fn index_from_config_files(name: &str) -> Result<Option<String>> {
+    for path in cargo_config_files() {
+        let Some(config) = CargoConfig::load(&path)? else {
+            continue;
+        };
+        if let Some(index) = config.registry(Some(name)).and_then(|r| r.index) {
+            return Ok(Some(index));
+        }
+    }
+    Ok(None)
+}
+
+/// `$CARGO_HOME`, or `~/.cargo` when unset, like cargo.
+pub fn cargo_home() -> PathBuf {
+    match std::env::var_os("CARGO_HOME").filter(|v| !v.is_empty()) {
+        Some(home) => PathBuf::from(home),
+        None => dirs::home_dir().unwrap_or_else(|| PathBuf::from(".")).join(".cargo"),
+    }
+}
+
+/// Cargo config files in the order cargo gives them precedence: the current
+/// directory and its ancestors, then `$CARGO_HOME`. Within a directory the
+/// extensionless legacy name comes first, as cargo prefers it when both
+/// exist.
+pub fn cargo_config_files() -> Vec<PathBuf> {
+    let mut dirs: Vec<PathBuf> = std::env::current_dir()
+        .map(|cwd| cwd.ancestors().map(|dir| dir.join(".cargo")).collect())
+        .unwrap_or_default();
+    let home = cargo_home();
+    if !dirs.contains(&home) {
+        dirs.push(home);
+    }
+
+    dirs.into_iter()
+        .flat_map(|dir| [dir.join("config"), dir.join("config.toml")])
+        .collect()
+}
+
+fn normalize_index_url(url: &str) -> Result<String> {
+    let url = url.strip_prefix("sparse+").unwrap_or(url);
+    if !url.starts_with("https://") && !url.starts_with("http://") {
+        bail!("Only sparse (HTTP) registry indexes are supported, got `{}`", url);
+    }
+    Ok(format!("{}/", url.trim_end_matches('/')))
+}
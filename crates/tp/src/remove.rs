@@ -0,0 +1,259 @@
+//! `tp remove`: delete trusted publishing configurations of workspace crates,
+//! e.g. ones pointing at an old repository or a mistyped workflow name.
+
+use std::collections::HashSet;
+
+use color_eyre::eyre::{Result, WrapErr, bail};
+use owo_colors::OwoColorize;
+
+use crate::api::{ApiClient, TrustpubConfig, delete_trustpub_config, list_trustpub_configs};
+use crate::exit::Outcome;
+use crate::{lock, owners};
+use crate::prompt::Prompt;
+use crate::registry::Registry;
+use crate::report::Report;
+use crate::select::PackageArgs;
+use crate::{
+    Forge, Package, cache_key, check_published, load_workspace, load_cache, progress_bar, resolve_token, save_cache,
+};
+
+/// Which configurations to remove. Every field that is set must match.
+pub struct RemoveFilter {
+    pub owner: Option<String>,
+    pub repo: Option<String>,
+    pub workflow: Option<String>,
+    /// Which crates' configurations to look at
+    pub packages: PackageArgs,
+}
+
+impl RemoveFilter {
+    fn is_empty(&self) -> bool {
+        self.owner.is_none() && self.repo.is_none() && self.workflow.is_none() && self.packages.is_empty()
+    }
+
+    fn matches(&self, cfg: &TrustpubConfig) -> bool {
+        self.owner.as_ref().is_none_or(|o| cfg.owner == *o)
+            && self.repo.as_ref().is_none_or(|r| cfg.repo == *r)
+            && self.workflow.as_ref().is_none_or(|w| cfg.workflow == *w)
+    }
+}
+
+pub async fn remove(
+    filter: &RemoveFilter,
+    token_env: Option<&str>,
+    registry: &Registry,
+    prompt: &Prompt,
+    report: &mut Report,
+    all: bool,
+    dry_run: bool,
+) -> Result<Outcome> {
+    report.dry_run = dry_run;
+    report.owner = filter.owner.clone();
+    report.repo = filter.repo.clone();
+    report.workflow = filter.workflow.clone();
+
+    if filter.is_empty() && !all {
+        bail!(
+            "Specify an owner, repo, workflow (-w) or crates (--package/--exclude) to filter on, or pass --all to remove every configuration"
+        );
+    }
+
+    let token = resolve_token(token_env, registry, report)?;
+
+    let packages = load_workspace(registry, &filter.packages, report)?.packages;
+    if packages.is_empty() {
+        say!("{}", "No publishable crates found.".yellow());
+        return Ok(Outcome::NothingToDo);
+    }
+
+    let client = ApiClient::new(registry).await?;
+
+    // Unpublished crates can't have configurations, and crates.io refuses to
+    // list them.
+    let published = check_published(&client, &packages).await?;
+    let published_packages: Vec<Package> = packages
+        .iter()
+        .filter(|pkg| published.get(&pkg.name) == Some(&true))
+        .cloned()
+        .collect();
+
+    say!("{}", "🔍 Checking existing configurations...".cyan());
+    let listing = list_trustpub_configs(&client, &token, &published_packages, &Forge::ALL).await?;
+    owners::print_refused(&listing.not_owned, client.label(), report);
+
+    let (to_remove, to_keep): (Vec<_>, Vec<_>) = listing.configs.into_iter().partition(|cfg| filter.matches(cfg));
+    // Like `lock` refuses to lock a crate without a configuration, a locked
+    // crate's last configuration stays
+    let locked = locked_out(&client, &published_packages, &to_remove, &to_keep).await?;
+    let (mut to_remove, mut refused): (Vec<_>, Vec<_>) =
+        to_remove.into_iter().partition(|cfg| !locked.contains(&cfg.crate_name));
+    to_remove.sort_by(|a, b| a.crate_name.cmp(&b.crate_name));
+    refused.sort_by(|a, b| a.crate_name.cmp(&b.crate_name));
+    for cfg in &to_remove {
+        report.add_config(cfg, Some(if dry_run { "would-remove" } else { "pending" }));
+    }
+    for cfg in &refused {
+        report.add_config(cfg, Some("kept"));
+    }
+
+    if !refused.is_empty() {
+        say!("\n{}", "🔒 Kept: the last configuration of a crate with trusted publishing only".yellow());
+        for cfg in &refused {
+            say!(
+                "     {} {} {} {}{}",
+                "•".dimmed(),
+                cfg.crate_name.cyan(),
+                cfg.repo_label().green(),
+                cfg.workflow.yellow(),
+                cfg.environment_label().yellow()
+            );
+        }
+        say!(
+            "     {}",
+            format!(
+                "(removing it would leave no way to publish the crate; turn trusted publishing only off on {} first)",
+                client.label()
+            )
+            .dimmed()
+        );
+    }
+
+    if to_remove.is_empty() {
+        if !refused.is_empty() {
+            return Ok(Outcome::PartialFailure);
+        }
+        say!("\n{} No matching configurations found.", "✓".green());
+        return Ok(Outcome::Success);
+    }
+
+    say!(
+        "\n🗑️  Will remove {} configuration{}:",
+        to_remove.len().to_string().bright_white().bold(),
+        if to_remove.len() == 1 { "" } else { "s" }
+    );
+    for cfg in &to_remove {
+        say!(
+            "     {} {} {} {}{} {}",
+            "•".dimmed(),
+            cfg.crate_name.cyan(),
+            cfg.repo_label().green(),
+            cfg.workflow.yellow(),
+            cfg.environment_label().yellow(),
+            format!("(id {})", cfg.id).dimmed()
+        );
+    }
+    say!();
+
+    if dry_run {
+        say!(
+            "{} Would remove {} configuration{}.",
+            "(dry run)".dimmed(),
+            to_remove.len().to_string().bright_white(),
+            if to_remove.len() == 1 { "" } else { "s" }
+        );
+        return Ok(if refused.is_empty() { Outcome::Success } else { Outcome::PartialFailure });
+    }
+
+    if !prompt.confirm("Remove these configurations?")? {
+        for cfg in &to_remove {
+            report.set_config_state(cfg, "skipped");
+        }
+        say!("{}", "Aborted.".yellow());
+        return Ok(Outcome::Aborted);
+    }
+
+    let pb = progress_bar(to_remove.len() as u64);
+
+    let mut errors = Vec::new();
+    let mut removed = Vec::new();
+    for cfg in &to_remove {
+        pb.set_message(format!("Removing {}", cfg.crate_name));
+        match delete_trustpub_config(&client, &token, cfg).await {
+            Ok(()) => {
+                report.set_config_state(cfg, "removed");
+                removed.push(cfg);
+            }
+            Err(e) => {
+                report.set_config_state(cfg, "failed");
+                report.add_error(&cfg.crate_name, &e.to_string());
+                errors.push((cfg.crate_name.clone(), e.to_string()));
+            }
+        }
+        pb.inc(1);
+    }
+    pb.finish_and_clear();
+
+    // Forget cached (owner, repo, crate) entries that no longer have any
+    // configuration behind them.
+    let remaining: HashSet<String> = to_keep
+        .iter()
+        .map(|cfg| cache_key(&cfg.owner, &cfg.repo, &cfg.crate_name))
+        .collect();
+    let mut cache = load_cache();
+    for cfg in &removed {
+        let key = cache_key(&cfg.owner, &cfg.repo, &cfg.crate_name);
+        if !remaining.contains(&key) {
+            cache.configured.remove(&key);
+        }
+    }
+    if let Err(e) = save_cache(&cache) {
+        eprintln!("{} could not save cache: {}", "⚠️  Warning:".yellow(), e);
+    }
+
+    if !errors.is_empty() {
+        say!("\n{}", "❌ Errors removing trusted publishing configurations:".red());
+        for (name, err) in &errors {
+            say!("   {} {} {}", name.cyan(), "✗".red(), err.dimmed());
+        }
+    }
+
+    if errors.is_empty() {
+        say!(
+            "\n{} Removed {} configuration{}.",
+            "✅".green(),
+            removed.len().to_string().bright_white().bold(),
+            if removed.len() == 1 { "" } else { "s" }
+        );
+    } else {
+        say!(
+            "\n{} Removed {}/{} configuration{}.",
+            "⚠️".yellow(),
+            removed.len().to_string().green(),
+            to_remove.len(),
+            if to_remove.len() == 1 { "" } else { "s" }
+        );
+    }
+
+    if errors.is_empty() && refused.is_empty() {
+        Ok(Outcome::Success)
+    } else {
+        Ok(Outcome::PartialFailure)
+    }
+}
+
+/// The crates with "trusted publishing only" on that removing `to_remove`
+/// would leave without any configuration, and so with no way to publish.
+async fn locked_out(
+    client: &ApiClient,
+    packages: &[Package],
+    to_remove: &[TrustpubConfig],
+    to_keep: &[TrustpubConfig],
+) -> Result<HashSet<String>> {
+    let left: HashSet<&str> = to_keep.iter().map(|cfg| cfg.crate_name.as_str()).collect();
+    let emptied: Vec<&Package> = packages
+        .iter()
+        .filter(|pkg| !left.contains(pkg.name.as_str()) && to_remove.iter().any(|cfg| cfg.crate_name == pkg.name))
+        .collect();
+    if emptied.is_empty() {
+        return Ok(HashSet::new());
+    }
+
+    let mut locked = HashSet::new();
+    for (pkg, state) in lock::lookup(client, &emptied).await {
+        let state = state.wrap_err_with(|| format!("Could not check whether {} is locked", pkg.name))?;
+        if state == Some(true) {
+            locked.insert(pkg.name.clone());
+        }
+    }
+    Ok(locked)
+}
@@ -0,0 +1,189 @@
+//! Machine-readable output. With `--format json`, a run collects what it did
+//! into a [`Report`] and prints it as a single JSON document on stdout; the
+//! human-readable text goes to stderr instead (see `say!`) and progress bars
+//! are hidden.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use color_eyre::eyre::{Result, bail};
+use facet::Facet;
+use figue as args;
+
+use crate::Package;
+use crate::api::TrustpubConfig;
+use crate::exit::{self, Outcome};
+
+static JSON: AtomicBool = AtomicBool::new(false);
+
+#[derive(Facet, Debug, Default)]
+pub struct OutputArgs {
+    /// Output format: "text" (default) or "json"
+    #[facet(args::named)]
+    pub format: Option<String>,
+}
+
+/// Switches the whole process to JSON output if requested.
+pub fn init(args: &OutputArgs) -> Result<()> {
+    match args.format.as_deref() {
+        None | Some("text") => {}
+        Some("json") => JSON.store(true, Ordering::Relaxed),
+        Some(other) => bail!("Unknown output format `{}`; expected `text` or `json`", other),
+    }
+    Ok(())
+}
+
+pub fn is_json() -> bool {
+    JSON.load(Ordering::Relaxed)
+}
+
+#[derive(Facet, Debug, Default)]
+pub struct Report {
+    pub command: String,
+    pub dry_run: bool,
+    /// Target registry, when it isn't crates.io
+    pub registry: Option<String>,
+    /// "github" or "gitlab"
+    pub forge: Option<String>,
+    pub owner: Option<String>,
+    pub repo: Option<String>,
+    pub workflow: Option<String>,
+    pub environment: Option<String>,
+    /// Where the token came from: an environment variable or a file path
+    pub token_source: Option<String>,
+    /// Login of the account the token acts as, when the registry says
+    pub user: Option<String>,
+    pub packages: Vec<PackageReport>,
+    pub configs: Vec<ConfigReport>,
+    /// What the workflow check before configuring found
+    pub workflow_checks: Vec<CheckReport>,
+    pub errors: Vec<ErrorReport>,
+    /// How the run ended, e.g. "success", "partial-failure", "auth-failure"
+    pub outcome: Option<String>,
+    /// Process exit code, see the `exit` module
+    pub exit_code: u8,
+    /// Set when the run as a whole failed
+    pub error: Option<String>,
+}
+
+#[derive(Facet, Debug)]
+pub struct PackageReport {
+    pub name: String,
+    pub version: String,
+    /// `None` when crates.io couldn't be asked
+    pub published: Option<bool>,
+    /// Whether a configuration matching the desired one exists after the run
+    pub configured: bool,
+    /// Whether the crate refuses publishes with an API token ("trusted
+    /// publishing only"); `None` when not looked up
+    pub trustpub_only: Option<bool>,
+    /// What the run did for this crate, e.g. "already-configured",
+    /// "configured", "would-configure", "failed", "skipped", "skipped-by-user",
+    /// or why it was left out: "publishes-elsewhere", "skipped-by-metadata",
+    /// "not-selected", "not-owned"; for `lock`: "locked", "already-locked",
+    /// "would-lock", "not-configured"
+    pub result: Option<String>,
+}
+
+impl PackageReport {
+    pub fn new(pkg: &Package) -> Self {
+        PackageReport {
+            name: pkg.name.clone(),
+            version: pkg.version.clone(),
+            published: None,
+            configured: false,
+            trustpub_only: None,
+            result: None,
+        }
+    }
+}
+
+#[derive(Facet, Debug)]
+pub struct ConfigReport {
+    pub id: u64,
+    #[facet(rename = "crate")]
+    pub crate_name: String,
+    pub forge: String,
+    pub owner: String,
+    pub repo: String,
+    pub workflow: String,
+    pub environment: Option<String>,
+    /// e.g. "correct", "stray", "pruned", "kept", "removed", "would-remove", "failed"
+    pub state: Option<String>,
+}
+
+#[derive(Facet, Debug)]
+pub struct CheckReport {
+    pub workflow: String,
+    /// "publish-job", "id-token", "token-exchange" or "token-secret"
+    pub check: String,
+    pub passed: bool,
+    pub message: String,
+}
+
+#[derive(Facet, Debug)]
+pub struct ErrorReport {
+    #[facet(rename = "crate")]
+    pub crate_name: String,
+    pub error: String,
+}
+
+impl Report {
+    pub fn new(command: &str) -> Self {
+        Report {
+            command: command.to_string(),
+            ..Default::default()
+        }
+    }
+
+    pub fn package_mut(&mut self, name: &str) -> Option<&mut PackageReport> {
+        self.packages.iter_mut().find(|p| p.name == name)
+    }
+
+    pub fn add_config(&mut self, cfg: &TrustpubConfig, state: Option<&str>) {
+        self.configs.push(ConfigReport {
+            id: cfg.id,
+            crate_name: cfg.crate_name.clone(),
+            forge: cfg.forge.id().to_string(),
+            owner: cfg.owner.clone(),
+            repo: cfg.repo.clone(),
+            workflow: cfg.workflow.clone(),
+            environment: cfg.environment.clone(),
+            state: state.map(str::to_string),
+        });
+    }
+
+    pub fn set_config_state(&mut self, config: &TrustpubConfig, state: &str) {
+        let forge = config.forge.id();
+        if let Some(cfg) = self.configs.iter_mut().find(|c| c.id == config.id && c.forge == forge) {
+            cfg.state = Some(state.to_string());
+        }
+    }
+
+    pub fn add_error(&mut self, crate_name: &str, error: &str) {
+        self.errors.push(ErrorReport {
+            crate_name: crate_name.to_string(),
+            error: error.to_string(),
+        });
+    }
+
+    /// Records the outcome of the run, prints the report if JSON output was
+    /// requested and passes the outcome through.
+    pub fn finish(mut self, result: Result<Outcome>) -> Result<Outcome> {
+        match &result {
+            Ok(outcome) => {
+                self.outcome = Some(outcome.id().to_string());
+                self.exit_code = outcome.code();
+            }
+            Err(e) => {
+                let (code, id) = exit::classify(e);
+                self.outcome = Some(id.to_string());
+                self.exit_code = code;
+                self.error = Some(e.to_string());
+            }
+        }
+        if is_json() {
+            println!("{}", facet_json::to_string(&self)?);
+        }
+        result
+    }
+}
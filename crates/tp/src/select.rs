@@ -0,0 +1,119 @@
+//! `--package`/`--exclude`: narrowing a run down to some of the workspace's
+//! crates, e.g. to onboard only new crates or leave experimental ones alone.
+
+use color_eyre::eyre::{Result, bail};
+use facet::Facet;
+use figue as args;
+
+use crate::Package;
+
+#[derive(Facet, Debug, Default)]
+pub struct PackageArgs {
+    /// Only work on crates matching this glob (e.g. "facet-*"); can be repeated
+    #[facet(args::named, args::short = 'p', default)]
+    pub package: Vec<String>,
+
+    /// Leave out crates matching this glob; can be repeated
+    #[facet(args::named, default)]
+    pub exclude: Vec<String>,
+}
+
+impl PackageArgs {
+    pub fn is_empty(&self) -> bool {
+        self.package.is_empty() && self.exclude.is_empty()
+    }
+
+    /// Splits `packages` into the selected ones and the rest. A `--package`
+    /// pattern that matches no crate at all is an error: it is most likely a
+    /// typo, and silently doing nothing would hide it.
+    pub fn apply(&self, packages: Vec<Package>) -> Result<(Vec<Package>, Vec<Package>)> {
+        for pattern in &self.package {
+            if !packages.iter().any(|pkg| glob_match(pattern, &pkg.name)) {
+                bail!("--package {} matches no publishable crate", pattern);
+            }
+        }
+
+        Ok(packages.into_iter().partition(|pkg| {
+            let included = self.package.is_empty() || self.package.iter().any(|p| glob_match(p, &pkg.name));
+            included && !self.exclude.iter().any(|p| glob_match(p, &pkg.name))
+        }))
+    }
+}
+
+/// Shell-style matching of a whole crate name: `*` matches any run of
+/// characters, `?` exactly one.
+fn glob_match(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+
+    let (mut p, mut n) = (0, 0);
+    // Where the last `*` was, and how much of the name it has swallowed
+    let mut star: Option<(usize, usize)> = None;
+    while n < name.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p, n));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == name[n] => {
+                p += 1;
+                n += 1;
+            }
+            _ => match star {
+                Some((sp, sn)) => {
+                    p = sp + 1;
+                    n = sn + 1;
+                    star = Some((sp, sn + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::glob_match;
+
+    #[test]
+    fn star_anywhere() {
+        assert!(glob_match("*-derive", "facet-derive"));
+        assert!(glob_match("facet-*-impl", "facet-json-impl"));
+        assert!(glob_match("facet-*", "facet-json"));
+        assert!(glob_match("facet-*", "facet-"));
+        assert!(glob_match("*", "facet"));
+    }
+
+    #[test]
+    fn question_mark_matches_one_char() {
+        assert!(glob_match("facet-?son", "facet-json"));
+        assert!(!glob_match("facet-?son", "facet-son"));
+        assert!(!glob_match("facet-?", "facet-js"));
+    }
+
+    #[test]
+    fn several_stars() {
+        assert!(glob_match("*a*e*", "facet-serde"));
+        assert!(glob_match("f**t", "facet"));
+        // The first `*` has to give back what it swallowed for the match to work
+        assert!(glob_match("*-*-core", "facet-json-core-core"));
+        assert!(!glob_match("*-*-core", "facet-core"));
+    }
+
+    #[test]
+    fn whole_name_must_match() {
+        assert!(!glob_match("facet", "facet-json"));
+        assert!(!glob_match("json", "facet-json"));
+        assert!(!glob_match("facet-*", "serde"));
+    }
+
+    #[test]
+    fn empty_pattern_or_name() {
+        assert!(glob_match("", ""));
+        assert!(glob_match("*", ""));
+        assert!(!glob_match("", "facet"));
+        assert!(!glob_match("?", ""));
+        assert!(!glob_match("facet", ""));
+    }
+}
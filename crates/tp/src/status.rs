@@ -0,0 +1,235 @@
+//! `tp status`: a read-only report of the trusted publishing state of every
+//! publishable crate in the workspace. Never prompts and never writes anything,
+//! so it is safe to run in CI.
+
+use std::collections::HashMap;
+
+use color_eyre::eyre::Result;
+use owo_colors::OwoColorize;
+
+use crate::api::{ApiClient, TrustpubConfig, list_trustpub_configs};
+use crate::exit::Outcome;
+use crate::overrides::Overrides;
+use crate::registry::Registry;
+use crate::report::{PackageReport, Report};
+use crate::sync::Desired;
+use crate::{
+    Forge, Package, TargetArgs, check_published, detected_repo, load_workspace, lock, pinned_repo, resolve_token,
+};
+
+pub async fn status(
+    target: &TargetArgs,
+    config: &Overrides,
+    registry: &Registry,
+    report: &mut Report,
+    check: bool,
+) -> Result<Outcome> {
+    let workspace = load_workspace(registry, &target.packages, report)?;
+    let chosen = target.over(&workspace.defaults.or(config));
+
+    // Unlike setup, a missing remote is not fatal here: we can still list
+    // whatever configurations exist, we just can't tell which ones are "ours".
+    let repo = match pinned_repo(target, &workspace.defaults, config)? {
+        Some(repo) => Some(repo),
+        None => match detected_repo(target) {
+            Ok(repo) => Some(repo),
+            Err(e) => {
+                say!("{} {}", "⚠️  Could not detect repo:".yellow(), e.to_string().dimmed());
+                None
+            }
+        },
+    };
+    if let Some((forge, owner, repo)) = &repo {
+        report.forge = Some(forge.id().to_string());
+        report.owner = Some(owner.clone());
+        report.repo = Some(repo.clone());
+    }
+    let workflow = chosen.workflow.as_ref();
+    let environment = chosen.environment.as_ref();
+    report.workflow = workflow.cloned();
+    report.environment = environment.cloned();
+    if let Some(w) = workflow {
+        say!("{} {}", "⚙️  Workflow:".cyan(), w.yellow());
+    }
+    say!();
+
+    let token = resolve_token(target.token_env.as_deref(), registry, report)?;
+
+    let packages = workspace.packages;
+    report.packages.extend(packages.iter().map(PackageReport::new));
+    if packages.is_empty() {
+        say!("{}", "No publishable crates found.".yellow());
+        return Ok(Outcome::NothingToDo);
+    }
+
+    let client = ApiClient::new(registry).await?;
+
+    // A failed lookup would show the crate as unconfigured, which `--check`
+    // must not report as a missing configuration
+    let published = check_published(&client, &packages).await?;
+
+    // Configurations can only be listed for crates that exist on crates.io.
+    let published_packages: Vec<Package> = packages
+        .iter()
+        .filter(|pkg| published.get(&pkg.name) == Some(&true))
+        .cloned()
+        .collect();
+    let listing = list_trustpub_configs(&client, &token, &published_packages, &Forge::ALL).await?;
+    let configs = listing.configs;
+
+    // Whether each crate refuses token publishes; unknown when the lookup
+    // failed or the registry has no such setting
+    let published_refs: Vec<&Package> = published_packages.iter().collect();
+    let locked: HashMap<String, Option<bool>> = lock::lookup(&client, &published_refs)
+        .await
+        .into_iter()
+        .map(|(pkg, state)| (pkg.name.clone(), state.ok().flatten()))
+        .collect();
+
+    let mut configs_by_crate: HashMap<&str, Vec<&TrustpubConfig>> = HashMap::new();
+    for cfg in &configs {
+        configs_by_crate.entry(cfg.crate_name.as_str()).or_default().push(cfg);
+    }
+
+    // Whether a configuration is the one the crate should have, `None` when
+    // that isn't known. The workflow and environment only narrow things down
+    // when given; a crate's [package.metadata.tp] overrides apply as in setup.
+    let forge = if target.gitlab { Forge::Gitlab } else { Forge::Github };
+    let desired = match &repo {
+        Some((forge, owner, repo)) => Desired {
+            forge: *forge,
+            owner,
+            repo,
+            workflow: workflow.map(String::as_str),
+            environment: environment.map(String::as_str),
+        },
+        // Only crates naming their own repository can be told apart
+        None => Desired {
+            forge,
+            owner: "",
+            repo: "",
+            workflow: workflow.map(String::as_str),
+            environment: environment.map(String::as_str),
+        },
+    };
+    let is_ours = |pkg: &Package, cfg: &TrustpubConfig| -> Option<bool> {
+        let desired = desired.for_package(pkg);
+        if desired.owner.is_empty() {
+            return None;
+        }
+        Some(desired.allows(cfg))
+    };
+
+    for cfg in &configs {
+        let state = packages
+            .iter()
+            .find(|pkg| pkg.name == cfg.crate_name)
+            .and_then(|pkg| is_ours(pkg, cfg))
+            .map(|ours| if ours { "correct" } else { "stray" });
+        report.add_config(cfg, state);
+    }
+
+    let name_width = packages.iter().map(|p| p.name.len()).max().unwrap_or(0).max("Crate".len());
+
+    say!(
+        "{:<name_width$}  {:<9}  {:<10}  {:<6}  {}",
+        "Crate".bold(),
+        "Published".bold(),
+        "Configured".bold(),
+        "Locked".bold(),
+        "Trusted publishers".bold()
+    );
+
+    let mut configured_count = 0;
+    for pkg in &packages {
+        let crate_configs = configs_by_crate.get(pkg.name.as_str()).map(Vec::as_slice).unwrap_or(&[]);
+
+        let is_published = published.get(&pkg.name) == Some(&true);
+        let published_cell = if is_published {
+            format!("{:<9}", "yes".green())
+        } else {
+            format!("{:<9}", "no".red())
+        };
+
+        let configured = crate_configs.iter().any(|cfg| is_ours(pkg, cfg).unwrap_or(true));
+        if configured {
+            configured_count += 1;
+        }
+        let trustpub_only = locked.get(&pkg.name).copied().flatten();
+        if let Some(p) = report.package_mut(&pkg.name) {
+            p.published = Some(is_published);
+            p.configured = configured;
+            p.trustpub_only = trustpub_only;
+        }
+        let configured_cell = if listing.not_owned.contains(&pkg.name) {
+            format!("{:<10}", "?".yellow())
+        } else if configured {
+            format!("{:<10}", "yes".green())
+        } else if is_published {
+            format!("{:<10}", "no".red())
+        } else {
+            format!("{:<10}", "-".dimmed())
+        };
+
+        let locked_cell = match trustpub_only {
+            Some(true) => format!("{:<6}", "yes".green()),
+            Some(false) => format!("{:<6}", "no".yellow()),
+            None => format!("{:<6}", "-".dimmed()),
+        };
+
+        let publishers = crate_configs
+            .iter()
+            .map(|cfg| {
+                let text = format!("{} → {}{}", cfg.repo_label(), cfg.workflow, cfg.environment_label());
+                if is_ours(pkg, cfg).unwrap_or(true) {
+                    text
+                } else {
+                    text.dimmed().to_string()
+                }
+            })
+            .collect::<Vec<_>>()
+            .join(", ");
+
+        say!(
+            "{:<name_width$}  {}  {}  {}  {}",
+            pkg.name.cyan(),
+            published_cell,
+            configured_cell,
+            locked_cell,
+            publishers
+        );
+    }
+
+    if !listing.not_owned.is_empty() {
+        say!(
+            "\n{}",
+            format!(
+                "? {} won't show this token the configurations of {}: its account doesn't own {}, or the token's crate scopes leave {} out.",
+                registry.label(),
+                listing.not_owned.join(", "),
+                if listing.not_owned.len() == 1 { "it" } else { "them" },
+                if listing.not_owned.len() == 1 { "it" } else { "them" }
+            )
+            .dimmed()
+        );
+    }
+
+    say!(
+        "\n{}/{} crate{} configured.",
+        configured_count.to_string().bright_white().bold(),
+        packages.len(),
+        if packages.len() == 1 { "" } else { "s" }
+    );
+
+    if check && configured_count < packages.len() {
+        say!(
+            "{} {} crate{} without trusted publishing.",
+            "❌".red(),
+            (packages.len() - configured_count).to_string().bright_white().bold(),
+            if packages.len() - configured_count == 1 { "" } else { "s" }
+        );
+        return Ok(Outcome::CheckFailed);
+    }
+
+    Ok(Outcome::Success)
+}
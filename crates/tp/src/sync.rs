@@ -0,0 +1,244 @@
+//! Reconciles the desired trusted publishing configurations against what
+//! crates.io actually has. Every workspace crate should have exactly one
+//! configuration, pointing at the desired owner/repo/workflow/environment
+//! (the run's, unless `[package.metadata.tp]` overrides it); anything else
+//! attached to those crates is "stray".
+
+use owo_colors::OwoColorize;
+
+use crate::api::TrustpubConfig;
+use crate::{Forge, Package};
+
+/// The configuration every crate should end up with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Desired<'a> {
+    pub forge: Forge,
+    pub owner: &'a str,
+    pub repo: &'a str,
+    /// Always known when configuring; `tp status` leaves it open when no
+    /// workflow was given
+    pub workflow: Option<&'a str>,
+    pub environment: Option<&'a str>,
+}
+
+impl<'a> Desired<'a> {
+    /// The configuration `pkg` should end up with: this one, with the
+    /// crate's `[package.metadata.tp]` overrides applied. Overriding the
+    /// workflow also drops the environment unless the crate sets one too,
+    /// since an environment belongs to the workflow whose job runs in it.
+    pub fn for_package<'b>(&self, pkg: &'b Package) -> Desired<'b>
+    where
+        'a: 'b,
+    {
+        let Some(overrides) = pkg.overrides() else {
+            return *self;
+        };
+
+        let (owner, repo) = overrides.repository().unwrap_or((self.owner, self.repo));
+        let (workflow, environment) = match &overrides.workflow {
+            Some(workflow) => (Some(workflow.as_str()), overrides.environment.as_deref()),
+            None => (self.workflow, overrides.environment.as_deref().or(self.environment)),
+        };
+        Desired {
+            forge: self.forge,
+            owner,
+            repo,
+            workflow,
+            environment,
+        }
+    }
+
+    fn matches(&self, cfg: &TrustpubConfig) -> bool {
+        self.allows(cfg) && cfg.environment.as_deref() == self.environment
+    }
+
+    /// Whether `cfg` could be this configuration, as far as it is known: an
+    /// open workflow allows any, and so does an unset environment. This is
+    /// what `tp status` counts as configured.
+    pub fn allows(&self, cfg: &TrustpubConfig) -> bool {
+        cfg.forge == self.forge
+            && cfg.owner == self.owner
+            && cfg.repo == self.repo
+            && self.workflow.is_none_or(|w| cfg.workflow == w)
+            && self.environment.is_none_or(|e| cfg.environment.as_deref() == Some(e))
+    }
+}
+
+pub struct Plan<'a> {
+    /// Crates without a configuration matching the desired one
+    pub to_add: Vec<&'a Package>,
+    /// Existing configurations that match the desired one exactly
+    pub correct: Vec<&'a TrustpubConfig>,
+    /// Existing configurations that point at a different repository, workflow or environment
+    pub stray: Vec<&'a TrustpubConfig>,
+    /// Crates left out of `to_add` because they already have a configuration
+    /// for the desired repository, only with another workflow or environment
+    pub drifted: Vec<&'a Package>,
+}
+
+/// Sorts the existing configurations of `packages` against the desired one.
+/// Unless `replace`, a crate already configured for the desired repository
+/// is taken as set up even if its workflow or environment differ: adding the
+/// desired configuration next to it would leave both able to publish, so
+/// that drift is only fixed by pruning the stray one.
+pub fn plan<'a>(
+    packages: &'a [Package],
+    existing: &'a [TrustpubConfig],
+    desired: &Desired,
+    replace: bool,
+) -> Plan<'a> {
+    let mut plan = Plan {
+        to_add: Vec::new(),
+        correct: Vec::new(),
+        stray: Vec::new(),
+        drifted: Vec::new(),
+    };
+
+    for pkg in packages {
+        let desired = desired.for_package(pkg);
+        let mut found = false;
+        let mut same_repo = false;
+        for cfg in existing.iter().filter(|cfg| cfg.crate_name == pkg.name) {
+            if desired.matches(cfg) {
+                found = true;
+                plan.correct.push(cfg);
+            } else {
+                same_repo |= cfg.forge == desired.forge && cfg.owner == desired.owner && cfg.repo == desired.repo;
+                plan.stray.push(cfg);
+            }
+        }
+        if found {
+            continue;
+        }
+        if same_repo && !replace {
+            plan.drifted.push(pkg);
+        } else {
+            plan.to_add.push(pkg);
+        }
+    }
+
+    plan
+}
+
+pub fn print_drifted(drifted: &[&Package]) {
+    if drifted.is_empty() {
+        return;
+    }
+
+    let names: Vec<&str> = drifted.iter().map(|pkg| pkg.name.as_str()).collect();
+    say!(
+        "\n{} {} {}",
+        "⚠️ ".yellow(),
+        "Configured for this repository with another workflow or environment:".yellow(),
+        names.join(", ")
+    );
+    say!(
+        "   {}",
+        "(not given a second configuration; run `tp sync --prune` to replace the stray one)".dimmed()
+    );
+}
+
+pub fn print_stray(stray: &[&TrustpubConfig], prune: bool) {
+    if stray.is_empty() {
+        return;
+    }
+
+    say!(
+        "\n{} {} stray configuration{} (different repository, workflow or environment):",
+        "⚠️ ".yellow(),
+        stray.len().to_string().bright_white().bold(),
+        if stray.len() == 1 { "" } else { "s" }
+    );
+    for cfg in stray {
+        say!(
+            "     {} {} {} {}{}",
+            "•".dimmed(),
+            cfg.crate_name.cyan(),
+            cfg.repo_label().green(),
+            cfg.workflow.yellow(),
+            cfg.environment_label().yellow()
+        );
+    }
+    if prune {
+        say!("   {}", "(will be deleted)".dimmed());
+    } else {
+        say!("   {}", "(left alone; run `tp sync --prune` to delete them)".dimmed());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn package(name: &str) -> Package {
+        Package {
+            name: name.to_string(),
+            id: name.to_string(),
+            version: "1.0.0".to_string(),
+            description: None,
+            license: None,
+            repository: None,
+            publish: None,
+            metadata: None,
+        }
+    }
+
+    fn config(crate_name: &str, owner: &str, workflow: &str) -> TrustpubConfig {
+        TrustpubConfig {
+            id: 1,
+            forge: Forge::Github,
+            crate_name: crate_name.to_string(),
+            owner: owner.to_string(),
+            repo: "facet".to_string(),
+            workflow: workflow.to_string(),
+            environment: None,
+        }
+    }
+
+    const DESIRED: Desired = Desired {
+        forge: Forge::Github,
+        owner: "facet-rs",
+        repo: "facet",
+        workflow: Some("release.yml"),
+        environment: None,
+    };
+
+    #[test]
+    fn matching_configuration_is_correct() {
+        let packages = [package("facet")];
+        let existing = [config("facet", "facet-rs", "release.yml")];
+        let plan = plan(&packages, &existing, &DESIRED, false);
+        assert_eq!(plan.correct.len(), 1);
+        assert!(plan.to_add.is_empty() && plan.stray.is_empty() && plan.drifted.is_empty());
+    }
+
+    #[test]
+    fn other_workflow_is_not_doubled_without_replace() {
+        let packages = [package("facet")];
+        let existing = [config("facet", "facet-rs", "publish.yml")];
+        let plan = plan(&packages, &existing, &DESIRED, false);
+        assert!(plan.to_add.is_empty());
+        assert_eq!(plan.drifted.len(), 1);
+        assert_eq!(plan.stray.len(), 1);
+    }
+
+    #[test]
+    fn other_workflow_is_replaced_with_replace() {
+        let packages = [package("facet")];
+        let existing = [config("facet", "facet-rs", "publish.yml")];
+        let plan = plan(&packages, &existing, &DESIRED, true);
+        assert_eq!(plan.to_add.len(), 1);
+        assert!(plan.drifted.is_empty());
+        assert_eq!(plan.stray.len(), 1);
+    }
+
+    #[test]
+    fn other_repository_still_gets_one() {
+        let packages = [package("facet"), package("facet-core")];
+        let existing = [config("facet", "someone-else", "release.yml")];
+        let plan = plan(&packages, &existing, &DESIRED, false);
+        let added: Vec<&str> = plan.to_add.iter().map(|pkg| pkg.name.as_str()).collect();
+        assert_eq!(added, ["facet", "facet-core"]);
+        assert_eq!(plan.stray.len(), 1);
+    }
+}
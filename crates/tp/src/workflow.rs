@@ -0,0 +1,1386 @@
+//! Just enough understanding of GitHub Actions workflow files to answer the
+//! questions `tp` has about them. Workflows are kept as raw lines and walked
+//! by indentation rather than deserialized, so comments and formatting are
+//! never lost and oddly-shaped YAML degrades to "don't know" instead of an
+//! error.
+
+use std::path::{Path, PathBuf};
+
+use color_eyre::eyre::{Result, bail, eyre};
+use owo_colors::OwoColorize;
+
+pub fn workflows_dir() -> PathBuf {
+    PathBuf::from(".github/workflows")
+}
+
+/// Strings that give away a job which publishes to crates.io.
+const PUBLISH_MARKERS: &[&str] = &["cargo publish", "release-plz", "crates-io-auth-action"];
+
+/// Strings that give away the step doing the actual publishing.
+const PUBLISH_COMMANDS: &[&str] = &["cargo publish", "release-plz"];
+
+/// Strings that give away a step trading the job's OIDC token for a
+/// crates.io token: the official action or a hand-rolled request. release-plz
+/// does it by itself too, but only when it may and isn't handed a token; see
+/// [`Workflow::exchanges_token`].
+const TOKEN_EXCHANGE_MARKERS: &[&str] = &[
+    "rust-lang/crates-io-auth-action",
+    "ACTIONS_ID_TOKEN_REQUEST_URL",
+    "trusted_publishing/tokens",
+];
+
+/// The secret a token-based workflow publishes with.
+const TOKEN_SECRET: &str = "secrets.CARGO_REGISTRY_TOKEN";
+
+/// The step [`Workflow::migrate`] adds to trade the job's OIDC token for a
+/// crates.io token.
+const AUTH_ACTION: &str = "rust-lang/crates-io-auth-action@v1";
+
+/// How likely a workflow is to be the one publishing to crates.io.
+#[derive(Default)]
+pub struct Rank {
+    pub score: u32,
+    /// Whether the workflow publishes at all; only these are candidates for
+    /// picking it without asking
+    pub publishes: bool,
+    /// Why it got its score, for the picker
+    pub reasons: Vec<&'static str>,
+}
+
+/// The events of an `on:` section `tp` cares about.
+#[derive(Default)]
+struct Triggers {
+    tags: bool,
+    default_branch: bool,
+    release: bool,
+    dispatch: bool,
+    /// `workflow_call`: the workflow is reusable
+    call: bool,
+    /// Any event besides `workflow_call`
+    other: bool,
+}
+
+/// The outcome of one check of [`Workflow::lint`].
+pub struct Finding {
+    /// Stable name, as used in JSON output: "reusable", "publish-job",
+    /// "id-token", "token-exchange" or "token-secret"
+    pub check: &'static str,
+    pub passed: bool,
+    pub message: String,
+}
+
+pub struct Workflow {
+    lines: Vec<String>,
+}
+
+/// A token-based workflow rewritten by [`Workflow::migrate`].
+pub struct Migration {
+    pub workflow: Workflow,
+    /// What was changed, one entry per edit
+    pub changes: Vec<String>,
+    /// What couldn't be migrated and is left to do by hand
+    pub manual: Vec<String>,
+}
+
+/// One change to a workflow's lines. Edits are collected against the
+/// original lines and applied from the bottom up, so indices stay valid.
+enum Edit {
+    Replace(usize, String),
+    /// Lines to insert before the given line
+    Insert(usize, Vec<String>),
+}
+
+/// A job inside `jobs:`, as a range of lines of its workflow.
+pub struct Job {
+    /// Line of the `<id>:` key
+    start: usize,
+    /// One past the last line of the job
+    end: usize,
+}
+
+impl Workflow {
+    pub fn load(name: &str) -> Result<Self> {
+        Self::load_path(&workflows_dir().join(name))
+    }
+
+    fn load_path(path: &Path) -> Result<Self> {
+        let contents =
+            std::fs::read_to_string(path).map_err(|e| eyre!("Could not read {}: {}", path.display(), e))?;
+        Ok(Self::parse(&contents))
+    }
+
+    fn parse(contents: &str) -> Self {
+        Workflow {
+            lines: contents.lines().map(str::to_string).collect(),
+        }
+    }
+
+    /// Index of the first line after `start` that is more indented than
+    /// `indent`, and that line's indentation: the body of a mapping key.
+    fn body_indent(&self, start: usize, indent: usize) -> Option<usize> {
+        self.lines[start + 1..]
+            .iter()
+            .find(|l| !is_blank(l))
+            .map(|l| indent_of(l))
+            .filter(|&i| i > indent)
+    }
+
+    /// End (exclusive) of the block that starts at `start` with the given
+    /// key indentation.
+    fn block_end(&self, start: usize, indent: usize) -> usize {
+        self.lines[start + 1..]
+            .iter()
+            .position(|l| !is_blank(l) && indent_of(l) <= indent)
+            .map(|p| start + 1 + p)
+            .unwrap_or(self.lines.len())
+    }
+
+    pub fn jobs(&self) -> Vec<Job> {
+        let Some(jobs_line) = self
+            .lines
+            .iter()
+            .position(|l| indent_of(l) == 0 && key_of(l) == Some("jobs"))
+        else {
+            return Vec::new();
+        };
+        let Some(job_indent) = self.body_indent(jobs_line, 0) else {
+            return Vec::new();
+        };
+        let jobs_end = self.block_end(jobs_line, 0);
+
+        let mut jobs = Vec::new();
+        for i in jobs_line + 1..jobs_end {
+            let line = &self.lines[i];
+            if is_blank(line) || indent_of(line) != job_indent {
+                continue;
+            }
+            if key_of(line).is_some() {
+                jobs.push(Job {
+                    start: i,
+                    end: self.block_end(i, job_indent).min(jobs_end),
+                });
+            }
+        }
+        jobs
+    }
+
+    fn job_lines<'a>(&'a self, job: &Job) -> &'a [String] {
+        &self.lines[job.start..job.end]
+    }
+
+    /// The job's id, the key it is defined under.
+    pub fn job_id(&self, job: &Job) -> &str {
+        key_of(&self.lines[job.start]).unwrap_or_default()
+    }
+
+    fn job_contains(&self, job: &Job, markers: &[&str]) -> bool {
+        self.job_lines(job)
+            .iter()
+            .any(|l| !is_comment(l) && markers.iter().any(|m| l.contains(m)))
+    }
+
+    /// Like [`Workflow::job_contains`] with publishing markers, except that
+    /// release-plz doesn't count in a job that only opens release PRs.
+    fn job_contains_publishing(&self, job: &Job, markers: &[&str]) -> bool {
+        if !only_opens_release_prs(self.job_lines(job)) {
+            return self.job_contains(job, markers);
+        }
+        let markers: Vec<&str> = markers.iter().copied().filter(|&m| m != "release-plz").collect();
+        self.job_contains(job, &markers)
+    }
+
+    /// Whether the `permissions:` key at `indent` within `start..end` grants
+    /// `id-token: write`. `None` when there is no such key, in which case
+    /// the job falls back to the workflow's permissions.
+    fn grants_id_token(&self, start: usize, end: usize, indent: usize) -> Option<bool> {
+        let line = (start..end)
+            .find(|&i| indent_of(&self.lines[i]) == indent && key_of(&self.lines[i]) == Some("permissions"))?;
+
+        if let Some(value) = value_of(&self.lines[line]) {
+            // `write-all`, `read-all`, or a flow mapping like `{ id-token: write }`
+            let compact: String = value.chars().filter(|c| !c.is_whitespace()).collect();
+            return Some(compact == "write-all" || compact.contains("id-token:write"));
+        }
+        let inner = self.body_indent(line, indent)?;
+        Some(
+            (line + 1..self.block_end(line, indent).min(end))
+                .map(|i| &self.lines[i])
+                .any(|l| indent_of(l) == inner && key_of(l) == Some("id-token") && value_of(l).as_deref() == Some("write")),
+        )
+    }
+
+    /// Whether `job` may request an OIDC token, by its own `permissions:` or
+    /// else the workflow's.
+    fn job_grants_id_token(&self, job: &Job) -> bool {
+        let job_indent = indent_of(&self.lines[job.start]);
+        let job_grant = self
+            .body_indent(job.start, job_indent)
+            .and_then(|body| self.grants_id_token(job.start + 1, job.end, body));
+        job_grant
+            .or_else(|| self.grants_id_token(0, self.lines.len(), 0))
+            .unwrap_or(false)
+    }
+
+    /// Whether `job` is handed `secrets.CARGO_REGISTRY_TOKEN`, in its own
+    /// lines or the workflow's `env:`.
+    fn job_gets_secret(&self, job: &Job) -> bool {
+        self.job_lines(job).iter().any(|l| reads_secret(l)) || self.env_reads_secret()
+    }
+
+    /// Whether the workflow's `env:` reads `secrets.CARGO_REGISTRY_TOKEN`.
+    fn env_reads_secret(&self) -> bool {
+        (0..self.lines.len())
+            .find(|&i| indent_of(&self.lines[i]) == 0 && key_of(&self.lines[i]) == Some("env"))
+            .is_some_and(|line| self.lines[line..self.block_end(line, 0)].iter().any(|l| reads_secret(l)))
+    }
+
+    /// Whether `job` trades its OIDC token for a crates.io token: with an
+    /// exchange step, or by running release-plz, which does it by itself
+    /// when it is `granted` `id-token: write` and given no token.
+    fn exchanges_token(&self, job: &Job, granted: bool) -> bool {
+        if self.job_contains(job, TOKEN_EXCHANGE_MARKERS) {
+            return true;
+        }
+        granted && self.job_contains_publishing(job, &["release-plz"]) && !self.job_gets_secret(job)
+    }
+
+    /// Ranks the workflow by what it runs and when: publishing steps weigh
+    /// the most, then triggers a release would come from (tags, releases,
+    /// pushes to `default_branch`). Steps of the reusable workflows it calls
+    /// count as its own.
+    pub fn rank(&self, default_branch: &str) -> Rank {
+        let mut rank = Rank {
+            score: 0,
+            publishes: false,
+            reasons: Vec::new(),
+        };
+        let callees: Vec<Workflow> = self.calls().iter().filter_map(|f| Workflow::load(f).ok()).collect();
+        let lines: Vec<String> = self
+            .lines
+            .iter()
+            .chain(callees.iter().flat_map(|w| &w.lines))
+            .cloned()
+            .collect();
+        let has = |marker: &str| lines.iter().any(|l| !is_comment(l) && l.contains(marker));
+
+        if has("cargo publish") {
+            rank.score += 4;
+            rank.publishes = true;
+            rank.reasons.push("runs cargo publish");
+        }
+        if has("release-plz") {
+            if only_opens_release_prs(&lines) {
+                rank.reasons.push("only opens release-plz PRs");
+            } else {
+                rank.score += 4;
+                rank.publishes = true;
+                rank.reasons.push("runs release-plz release");
+            }
+        }
+        if has("crates-io-auth-action") {
+            rank.score += 3;
+            rank.publishes = true;
+            rank.reasons.push("uses crates-io-auth-action");
+        }
+
+        let triggers = self.triggers(default_branch);
+        if triggers.tags {
+            rank.score += 2;
+            rank.reasons.push("triggers on tags");
+        }
+        if triggers.release {
+            rank.score += 2;
+            rank.reasons.push("triggers on releases");
+        }
+        if triggers.default_branch {
+            rank.score += 1;
+            rank.reasons.push("triggers on pushes to the default branch");
+        }
+        if triggers.dispatch {
+            rank.reasons.push("can be run by hand");
+        }
+        if !callees.is_empty() {
+            rank.reasons.push("calls a reusable workflow");
+        }
+        rank
+    }
+
+    /// Whether the workflow only ever runs when another one calls it
+    /// (`on: workflow_call` and nothing else). crates.io checks the workflow
+    /// that started the run, so no configuration can name this one.
+    pub fn only_called(&self) -> bool {
+        let triggers = self.triggers("");
+        triggers.call && !triggers.other
+    }
+
+    /// The reusable workflows of this repository the jobs call, as
+    /// filenames in `.github/workflows`.
+    pub fn calls(&self) -> Vec<String> {
+        let mut calls: Vec<String> = self.jobs().iter().filter_map(|job| self.job_calls(job)).collect();
+        calls.sort();
+        calls.dedup();
+        calls
+    }
+
+    /// The reusable workflow a job calls with `uses: ./.github/workflows/<file>`.
+    fn job_calls(&self, job: &Job) -> Option<String> {
+        let job_indent = indent_of(&self.lines[job.start]);
+        let body = self.body_indent(job.start, job_indent)?;
+        let uses = (job.start + 1..job.end)
+            .find(|&i| indent_of(&self.lines[i]) == body && key_of(&self.lines[i]) == Some("uses"))?;
+        let target = value_of(&self.lines[uses])?;
+        let file = target.strip_prefix("./.github/workflows/")?;
+        Some(file.split('@').next().unwrap_or(file).to_string())
+    }
+
+    /// Reads the `on:` section, in any of its scalar, list or mapping forms.
+    fn triggers(&self, default_branch: &str) -> Triggers {
+        let mut triggers = Triggers::default();
+        // YAML 1.1 reads a bare `on` as `true`, and some files say so
+        let Some(on_line) = self
+            .lines
+            .iter()
+            .position(|l| indent_of(l) == 0 && matches!(key_of(l), Some("on") | Some("true")))
+        else {
+            return triggers;
+        };
+
+        let mut set = |event: &str, filters: Option<(usize, usize)>| {
+            if event == "workflow_call" {
+                triggers.call = true;
+                return;
+            }
+            triggers.other = true;
+            match event {
+                "release" => triggers.release = true,
+                "workflow_dispatch" => triggers.dispatch = true,
+                // A push without filters fires for every branch and tag
+                "push" => match filters {
+                    None => {
+                        triggers.tags = true;
+                        triggers.default_branch = true;
+                    }
+                    Some((start, end)) => {
+                        let lines = &self.lines[start..end];
+                        triggers.tags |= lines.iter().any(|l| key_of(l) == Some("tags"));
+                        triggers.default_branch |= branches(lines).iter().any(|b| {
+                            b == default_branch || b == "*" || b == "**"
+                        });
+                    }
+                },
+                _ => {}
+            }
+        };
+
+        if let Some(value) = value_of(&self.lines[on_line]) {
+            for event in value.trim_matches(|c| c == '[' || c == ']').split(',') {
+                set(event.trim().trim_matches(|c| c == '"' || c == '\''), None);
+            }
+            return triggers;
+        }
+
+        let Some(inner) = self.body_indent(on_line, 0) else {
+            return triggers;
+        };
+        let end = self.block_end(on_line, 0);
+        for i in on_line + 1..end {
+            let line = &self.lines[i];
+            if is_blank(line) || indent_of(line) != inner {
+                continue;
+            }
+            // `- push` in a block list
+            if let Some(item) = line.trim().strip_prefix("- ") {
+                set(item.trim(), None);
+                continue;
+            }
+            let Some(event) = key_of(line) else {
+                continue;
+            };
+            let body_end = self.block_end(i, inner).min(end);
+            let filters = (body_end > i + 1).then_some((i + 1, body_end));
+            set(event, filters);
+        }
+        triggers
+    }
+
+    /// Checks that the workflow is ready for trusted publishing: it isn't
+    /// only ever called by other workflows, a job publishes (itself or
+    /// through a reusable workflow it calls), that job may request an OIDC
+    /// token and trades it for a crates.io token, and no step still reads the
+    /// old token secret.
+    pub fn lint(&self) -> Vec<Finding> {
+        let jobs = self.jobs();
+        // A job calling a reusable workflow publishes if that one does; the
+        // caller still has to grant the OIDC token
+        let publishing: Vec<(&Job, Option<(String, Workflow)>)> = jobs
+            .iter()
+            .filter_map(|job| {
+                if self.job_contains_publishing(job, PUBLISH_COMMANDS) {
+                    return Some((job, None));
+                }
+                let file = self.job_calls(job)?;
+                let callee = Workflow::load(&file).ok()?;
+                let publishes = callee.jobs().iter().any(|j| callee.job_contains_publishing(j, PUBLISH_COMMANDS));
+                publishes.then_some((job, Some((file, callee))))
+            })
+            .collect();
+        let mut findings = Vec::new();
+
+        // A reusable workflow gets its token permissions from the caller
+        let reusable = self.only_called();
+        if reusable {
+            findings.push(Finding {
+                check: "reusable",
+                passed: false,
+                message: "only runs when another workflow calls it (`on: workflow_call`); crates.io checks the calling workflow's filename, so configure that one instead".to_string(),
+            });
+        }
+
+        if publishing.is_empty() {
+            findings.push(Finding {
+                check: "publish-job",
+                passed: false,
+                message: "no job runs `cargo publish` or release-plz".to_string(),
+            });
+        }
+
+        let workflow_grant = self.grants_id_token(0, self.lines.len(), 0);
+        if publishing.is_empty() && !reusable {
+            let granted = workflow_grant.unwrap_or(false);
+            findings.push(Finding {
+                check: "id-token",
+                passed: granted,
+                message: if granted {
+                    "the workflow grants `id-token: write`".to_string()
+                } else {
+                    "the workflow doesn't grant `permissions: id-token: write`".to_string()
+                },
+            });
+        }
+
+        for (job, callee) in &publishing {
+            let id = self.job_id(job);
+            findings.push(Finding {
+                check: "publish-job",
+                passed: true,
+                message: match callee {
+                    Some((file, _)) => format!("job `{}` publishes through {}", id, file),
+                    None => format!("job `{}` publishes", id),
+                },
+            });
+
+            // A reusable workflow's caller grants the token, which can't be
+            // checked from here
+            let granted = reusable || self.job_grants_id_token(job);
+            if !reusable {
+                findings.push(Finding {
+                    check: "id-token",
+                    passed: granted,
+                    message: if granted {
+                        format!("job `{}` may request an OIDC token (`id-token: write`)", id)
+                    } else {
+                        format!(
+                            "job `{}` lacks `permissions: id-token: write`, so it can't get a token from crates.io",
+                            id
+                        )
+                    },
+                });
+            }
+
+            let exchanges = match callee {
+                Some((_, callee)) => callee.jobs().iter().any(|j| callee.exchanges_token(j, granted)),
+                None => self.exchanges_token(job, granted),
+            };
+            findings.push(Finding {
+                check: "token-exchange",
+                passed: exchanges,
+                message: if exchanges {
+                    format!("job `{}` trades its OIDC token for a crates.io token", id)
+                } else {
+                    format!(
+                        "job `{}` never trades its OIDC token for a crates.io token; add a `rust-lang/crates-io-auth-action` step before publishing",
+                        id
+                    )
+                },
+            });
+        }
+
+        let leftovers: Vec<usize> = self
+            .lines
+            .iter()
+            .enumerate()
+            .filter(|(_, l)| reads_secret(l))
+            .map(|(i, _)| i + 1)
+            .collect();
+        findings.push(Finding {
+            check: "token-secret",
+            passed: leftovers.is_empty(),
+            message: if leftovers.is_empty() {
+                format!("no `{}` left", TOKEN_SECRET)
+            } else {
+                let lines: Vec<String> = leftovers.iter().map(usize::to_string).collect();
+                format!(
+                    "`{}` still used (line {}); drop it, and the secret, once trusted publishing works",
+                    TOKEN_SECRET,
+                    lines.join(", ")
+                )
+            },
+        });
+
+        findings
+    }
+
+    /// Whether the job looks like it publishes to crates.io.
+    pub fn job_publishes(&self, job: &Job) -> bool {
+        self.job_contains_publishing(job, PUBLISH_MARKERS)
+    }
+
+    /// The deployment environment the job runs in, either `environment: name`
+    /// or `environment: { name: ... }` in block form. Expressions are ignored
+    /// since crates.io needs a literal name.
+    pub fn job_environment(&self, job: &Job) -> Option<String> {
+        let job_indent = indent_of(&self.lines[job.start]);
+        let body = self.body_indent(job.start, job_indent)?;
+
+        let line = (job.start + 1..job.end)
+            .find(|&i| indent_of(&self.lines[i]) == body && key_of(&self.lines[i]) == Some("environment"))?;
+
+        let value = match value_of(&self.lines[line]) {
+            Some(v) => v,
+            None => {
+                let inner = self.body_indent(line, body)?;
+                (line + 1..self.block_end(line, body))
+                    .map(|i| &self.lines[i])
+                    .find(|l| indent_of(l) == inner && key_of(l) == Some("name"))
+                    .and_then(|l| value_of(l))?
+            }
+        };
+
+        if value.contains("${{") { None } else { Some(value) }
+    }
+
+    pub fn lines(&self) -> &[String] {
+        &self.lines
+    }
+
+    /// Moves the publishing jobs that read `secrets.CARGO_REGISTRY_TOKEN` in
+    /// their steps to trusted publishing: the job is granted `id-token:
+    /// write`, a `crates-io-auth-action` step is added before the first step
+    /// reading the secret, and the secret is replaced with that step's
+    /// output. Every other line is kept as it was, comments included.
+    pub fn migrate(&self) -> Result<Migration> {
+        let mut edits = Vec::new();
+        let mut changes = Vec::new();
+        let mut manual = Vec::new();
+
+        for job in self.jobs().iter().filter(|job| self.job_contains_publishing(job, PUBLISH_COMMANDS)) {
+            let id = self.job_id(job);
+            let job_indent = indent_of(&self.lines[job.start]);
+            let Some(body) = self.body_indent(job.start, job_indent) else {
+                continue;
+            };
+            let steps = (job.start + 1..job.end)
+                .find(|&i| indent_of(&self.lines[i]) == body && key_of(&self.lines[i]) == Some("steps"));
+            let Some(steps) = steps else {
+                continue;
+            };
+            // Items may sit at the same indentation as `steps:` itself
+            let Some(item_indent) = self.lines[steps + 1..job.end]
+                .iter()
+                .find(|l| !is_blank(l))
+                .filter(|l| l.trim_start().starts_with('-'))
+                .map(|l| indent_of(l))
+            else {
+                continue;
+            };
+            let step_start = |i: usize| {
+                (steps + 1..=i)
+                    .rev()
+                    .find(|&j| indent_of(&self.lines[j]) == item_indent && self.lines[j].trim_start().starts_with('-'))
+            };
+
+            // A job's or the workflow's `env:` is evaluated before any step
+            // runs, so the auth step's output can't replace the secret there
+            let steps_end = (steps + 1..job.end)
+                .find(|&i| {
+                    let l = &self.lines[i];
+                    !is_blank(l)
+                        && indent_of(l) <= body
+                        && !(indent_of(l) == item_indent && l.trim_start().starts_with('-'))
+                })
+                .unwrap_or(job.end);
+            let outside = (job.start..job.end)
+                .filter(|i| !(steps..steps_end).contains(i))
+                .any(|i| reads_secret(&self.lines[i]))
+                || self.env_reads_secret();
+            if outside {
+                manual.push(format!(
+                    "job `{}` reads `{}` outside its steps (in the job's or the workflow's `env:`), where a step's output isn't available; move it into the step that publishes",
+                    id, TOKEN_SECRET
+                ));
+            }
+
+            let uses: Vec<usize> = (steps + 1..steps_end).filter(|&i| reads_secret(&self.lines[i])).collect();
+            let Some(first) = uses.first().and_then(|&i| step_start(i)) else {
+                if outside {
+                    continue;
+                }
+                let granted = self.job_grants_id_token(job);
+                if self.exchanges_token(job, granted) {
+                    continue;
+                }
+                if self.job_contains_publishing(job, &["release-plz"]) && !self.job_gets_secret(job) {
+                    manual.push(format!(
+                        "job `{}` runs release-plz without `id-token: write`; grant it, and release-plz trades the OIDC token for a crates.io token by itself",
+                        id
+                    ));
+                } else {
+                    manual.push(format!(
+                        "job `{}` publishes without reading `{}` in its steps; give its publishing step the token of a `{}` step",
+                        id, TOKEN_SECRET, AUTH_ACTION
+                    ));
+                }
+                continue;
+            };
+
+            // An auth step already in place only needs an id to refer to
+            let step_ids: Vec<String> = (steps + 1..job.end)
+                .filter_map(|i| item_key(&self.lines[i]).filter(|&k| k == "id").and(value_of(&self.lines[i])))
+                .collect();
+            let existing = (steps + 1..first)
+                .find(|&i| !is_comment(&self.lines[i]) && self.lines[i].contains("crates-io-auth-action"))
+                .and_then(step_start);
+            let pad = " ".repeat(item_indent);
+            let step_id = match existing {
+                Some(start) => {
+                    let end = (start + 1..job.end)
+                        .find(|&j| indent_of(&self.lines[j]) <= item_indent && !is_blank(&self.lines[j]))
+                        .unwrap_or(job.end);
+                    match (start..end).find(|&i| item_key(&self.lines[i]) == Some("id")) {
+                        Some(i) => value_of(&self.lines[i]).unwrap_or_default(),
+                        None => {
+                            let step_id = unused_id(&step_ids);
+                            edits.push(Edit::Insert(start + 1, vec![format!("{}  id: {}", pad, step_id)]));
+                            changes.push(format!("job `{}`: gave its `crates-io-auth-action` step the id `{}`", id, step_id));
+                            step_id
+                        }
+                    }
+                }
+                None => {
+                    let step_id = unused_id(&step_ids);
+                    edits.push(Edit::Insert(
+                        first,
+                        vec![format!("{}- uses: {}", pad, AUTH_ACTION), format!("{}  id: {}", pad, step_id)],
+                    ));
+                    changes.push(format!("job `{}`: added a `{}` step", id, AUTH_ACTION));
+                    step_id
+                }
+            };
+
+            let output = format!("steps.{}.outputs.token", step_id);
+            for &i in &uses {
+                edits.push(Edit::Replace(i, self.lines[i].replace(TOKEN_SECRET, &output)));
+            }
+            changes.push(format!("job `{}`: publishes with `{}` instead of `{}`", id, output, TOKEN_SECRET));
+
+            if let Some(change) = self.grant_job_id_token(job, body, steps, &mut edits)? {
+                changes.push(format!("job `{}`: {}", id, change));
+            }
+        }
+
+        // Replacements don't move lines, so they go first; then insertions
+        // from the bottom up
+        let mut lines = self.lines.clone();
+        let mut inserts = Vec::new();
+        for edit in edits {
+            match edit {
+                Edit::Replace(i, line) => lines[i] = line,
+                Edit::Insert(i, new) => inserts.push((i, new)),
+            }
+        }
+        inserts.sort_by_key(|(i, _)| std::cmp::Reverse(*i));
+        for (i, new) in inserts {
+            lines.splice(i..i, new);
+        }
+
+        Ok(Migration {
+            workflow: Workflow { lines },
+            changes,
+            manual,
+        })
+    }
+
+    /// Queues the edits granting `job` `id-token: write`, describing them;
+    /// `None` if it already has it. A job with its own `permissions:` gets
+    /// the entry added there. Otherwise the job gets a `permissions:` block
+    /// before its `steps:` line, holding the workflow's permissions as well,
+    /// since a job's permissions replace the workflow's rather than adding
+    /// to them.
+    fn grant_job_id_token(&self, job: &Job, body: usize, steps: usize, edits: &mut Vec<Edit>) -> Result<Option<String>> {
+        let id = self.job_id(job);
+        let job_perms = (job.start + 1..job.end)
+            .find(|&i| indent_of(&self.lines[i]) == body && key_of(&self.lines[i]) == Some("permissions"));
+        if let Some(line) = job_perms {
+            return self.grant_id_token_at(line, body, &format!("job `{}`", id), edits);
+        }
+        if self.grants_id_token(0, self.lines.len(), 0) == Some(true) {
+            return Ok(None);
+        }
+
+        let pad = " ".repeat(body);
+        let nested = " ".repeat(body + (body - indent_of(&self.lines[job.start])));
+        let workflow_perms = (0..self.lines.len())
+            .find(|&i| indent_of(&self.lines[i]) == 0 && key_of(&self.lines[i]) == Some("permissions"));
+        let mut block = Vec::new();
+        let change = match workflow_perms {
+            None => {
+                block.push(format!("{}permissions:", pad));
+                block.push(format!("{}contents: read", nested));
+                block.push(format!("{}id-token: write", nested));
+                "granted `id-token: write` (and `contents: read`, for checking out the code)"
+            }
+            Some(line) => match value_of(&self.lines[line]) {
+                Some(value) if value.starts_with('{') => {
+                    block.push(format!("{}permissions: {}", pad, grant_in_flow(&value)));
+                    "granted `id-token: write`, along with the workflow's permissions"
+                }
+                Some(value) => bail!(
+                    "The workflow has `permissions: {}`; grant job `{}` `id-token: write` by hand",
+                    value,
+                    id
+                ),
+                None => {
+                    let inner = self.body_indent(line, 0).unwrap_or(2);
+                    block.push(format!("{}permissions:", pad));
+                    for l in &self.lines[line + 1..self.block_end(line, 0)] {
+                        if !is_blank(l) && key_of(l) != Some("id-token") {
+                            let depth = indent_of(l).saturating_sub(inner);
+                            block.push(format!("{}{}{}", nested, " ".repeat(depth), l.trim_start()));
+                        }
+                    }
+                    block.push(format!("{}id-token: write", nested));
+                    "granted `id-token: write`, along with the workflow's permissions"
+                }
+            },
+        };
+        edits.push(Edit::Insert(steps, block));
+        Ok(Some(change.to_string()))
+    }
+
+    /// Queues the edit granting `id-token: write` in the existing
+    /// `permissions:` key at `line`; `None` if it already does.
+    fn grant_id_token_at(&self, line: usize, indent: usize, what: &str, edits: &mut Vec<Edit>) -> Result<Option<String>> {
+        if self.grants_id_token(line, self.block_end(line, indent), indent) == Some(true) {
+            return Ok(None);
+        }
+        if let Some(value) = value_of(&self.lines[line]) {
+            if !value.starts_with('{') {
+                bail!("{} has `permissions: {}`; grant it `id-token: write` by hand", what, value);
+            }
+            let raw = &self.lines[line];
+            let key_end = raw.find(':').unwrap_or(raw.len()) + 1;
+            edits.push(Edit::Replace(line, format!("{} {}", &raw[..key_end], grant_in_flow(&value))));
+            return Ok(Some("granted `id-token: write`".to_string()));
+        }
+
+        let inner = self.body_indent(line, indent).unwrap_or(indent + 2);
+        let end = self.block_end(line, indent);
+        let entry = format!("{}id-token: write", " ".repeat(inner));
+        match (line + 1..end).find(|&i| indent_of(&self.lines[i]) == inner && key_of(&self.lines[i]) == Some("id-token")) {
+            Some(i) => edits.push(Edit::Replace(i, entry)),
+            None => {
+                let last = (line + 1..end).rev().find(|&i| !is_blank(&self.lines[i])).unwrap_or(line);
+                edits.push(Edit::Insert(last + 1, vec![entry]));
+            }
+        }
+        Ok(Some("granted `id-token: write`".to_string()))
+    }
+
+    /// The environment to suggest for this workflow: the one used by its
+    /// publishing job, or failing that the only one used by any job.
+    pub fn suggested_environment(&self) -> Option<String> {
+        let jobs = self.jobs();
+
+        let mut publishing: Vec<String> = jobs
+            .iter()
+            .filter(|job| self.job_publishes(job))
+            .filter_map(|job| self.job_environment(job))
+            .collect();
+        publishing.sort();
+        publishing.dedup();
+        if publishing.len() == 1 {
+            return publishing.pop();
+        }
+        if !publishing.is_empty() {
+            return None;
+        }
+
+        let mut all: Vec<String> = jobs.iter().filter_map(|job| self.job_environment(job)).collect();
+        all.sort();
+        all.dedup();
+        if all.len() == 1 { all.pop() } else { None }
+    }
+}
+
+fn indent_of(line: &str) -> usize {
+    line.len() - line.trim_start_matches(' ').len()
+}
+
+fn is_comment(line: &str) -> bool {
+    line.trim_start().starts_with('#')
+}
+
+/// Whether `line` reads `secrets.CARGO_REGISTRY_TOKEN`.
+fn reads_secret(line: &str) -> bool {
+    !is_comment(line) && line.contains(TOKEN_SECRET)
+}
+
+fn is_blank(line: &str) -> bool {
+    line.trim().is_empty() || is_comment(line)
+}
+
+/// The branch names under a `branches:` key among `lines`, from a flow list
+/// (`[main, next]`), a scalar or a block list.
+fn branches(lines: &[String]) -> Vec<String> {
+    let Some(pos) = lines.iter().position(|l| key_of(l) == Some("branches")) else {
+        return Vec::new();
+    };
+    let unquote = |s: &str| s.trim().trim_matches(|c| c == '"' || c == '\'').to_string();
+    if let Some(value) = value_of(&lines[pos]) {
+        return value.trim_matches(|c| c == '[' || c == ']').split(',').map(unquote).collect();
+    }
+    let indent = indent_of(&lines[pos]);
+    lines[pos + 1..]
+        .iter()
+        .filter(|l| !is_blank(l))
+        .take_while(|l| indent_of(l) > indent || (l.trim_start().starts_with("- ") && indent_of(l) == indent))
+        .filter_map(|l| l.trim().strip_prefix("- "))
+        .map(|b| unquote(b.split(" #").next().unwrap_or(b)))
+        .collect()
+}
+
+/// The release-plz action runs both of its commands unless told one; lines
+/// that only ever ask for `release-pr` never publish.
+fn only_opens_release_prs(lines: &[String]) -> bool {
+    let mut commands = lines
+        .iter()
+        .filter(|l| !is_comment(l) && key_of(l) == Some("command"))
+        .peekable();
+    commands.peek().is_some() && commands.all(|l| value_of(l).as_deref() == Some("release-pr"))
+}
+
+/// The key of a line, whether it is the first one of a list item
+/// (`- id: auth`) or not.
+fn item_key(line: &str) -> Option<&str> {
+    key_of(line.trim_start().trim_start_matches("- "))
+}
+
+/// An id for the auth step that no other step of the job uses.
+fn unused_id(taken: &[String]) -> String {
+    ["auth", "crates-io-auth"]
+        .into_iter()
+        .find(|id| !taken.iter().any(|t| t == id))
+        .unwrap_or("crates-io-auth-token")
+        .to_string()
+}
+
+/// `{ contents: read }` with `id-token: write` added, or set if present.
+fn grant_in_flow(value: &str) -> String {
+    let mut entries: Vec<&str> = value
+        .trim_matches(|c| c == '{' || c == '}')
+        .split(',')
+        .map(str::trim)
+        .filter(|e| !e.is_empty() && !e.starts_with("id-token"))
+        .collect();
+    entries.push("id-token: write");
+    format!("{{ {} }}", entries.join(", "))
+}
+
+/// The key of a `key: value` or `key:` line (list items excluded).
+fn key_of(line: &str) -> Option<&str> {
+    let trimmed = line.trim();
+    if trimmed.starts_with('-') || trimmed.starts_with('#') {
+        return None;
+    }
+    let (key, rest) = trimmed.split_once(':')?;
+    if !rest.is_empty() && !rest.starts_with(' ') {
+        return None;
+    }
+    Some(key.trim().trim_matches(|c| c == '"' || c == '\''))
+}
+
+/// The scalar value of a `key: value` line, without quotes or trailing
+/// comment. `None` when the value is a nested block.
+fn value_of(line: &str) -> Option<String> {
+    let (_, rest) = line.trim().split_once(':')?;
+    let rest = match rest.find(" #") {
+        Some(pos) => &rest[..pos],
+        None => rest,
+    };
+    let value = rest.trim().trim_matches(|c| c == '"' || c == '\'');
+    if value.is_empty() { None } else { Some(value.to_string()) }
+}
+
+/// The workflows among `files` that call the reusable workflow `name`.
+pub fn callers(name: &str, files: &[String]) -> Vec<String> {
+    files
+        .iter()
+        .filter(|f| *f != name)
+        .filter(|f| Workflow::load(f).is_ok_and(|w| w.calls().iter().any(|c| c == name)))
+        .cloned()
+        .collect()
+}
+
+/// Prints the findings of [`Workflow::lint`] for the workflow `name`.
+pub fn print_findings(name: &str, findings: &[Finding]) {
+    say!("{} {}", "🩺 Workflow check:".cyan(), name.yellow());
+    for finding in findings {
+        if finding.passed {
+            say!("   {} {}", "✓".green(), finding.message.dimmed());
+        } else {
+            say!("   {} {}", "✗".red(), finding.message);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn finding(workflow: &str, check: &str) -> bool {
+        Workflow::parse(workflow)
+            .lint()
+            .into_iter()
+            .find(|f| f.check == check && f.message.contains("job"))
+            .map(|f| f.passed)
+            .unwrap_or_else(|| panic!("no `{}` finding", check))
+    }
+
+    const RELEASE_PLZ: &str = "\
+on:
+  push:
+    branches: [main]
+jobs:
+  release:
+    runs-on: ubuntu-latest
+    permissions:
+      contents: write
+      id-token: write
+    steps:
+      - uses: release-plz/action@v0.5
+        with:
+          command: release
+";
+
+    #[test]
+    fn release_plz_exchanges_when_granted() {
+        assert!(finding(RELEASE_PLZ, "token-exchange"));
+    }
+
+    #[test]
+    fn release_plz_without_id_token_does_not_exchange() {
+        let workflow = r#"on:
+  push:
+    branches: [main]
+jobs:
+  release:
+    runs-on: ubuntu-latest
+    permissions:
+      contents: write
+    steps:
+      - uses: release-plz/action@v0.5
+        with:
+          command: release
+"#;
+        assert!(!finding(workflow, "token-exchange"));
+    }
+
+    #[test]
+    fn release_plz_given_the_secret_does_not_exchange() {
+        let workflow = r#"on:
+  push:
+    branches: [main]
+jobs:
+  release:
+    runs-on: ubuntu-latest
+    permissions:
+      contents: write
+      id-token: write
+    steps:
+      - uses: release-plz/action@v0.5
+        with:
+          command: release
+        env:
+          CARGO_REGISTRY_TOKEN: ${{ secrets.CARGO_REGISTRY_TOKEN }}
+"#;
+        assert!(!finding(workflow, "token-exchange"));
+
+        let workflow = r#"on:
+  push:
+    branches: [main]
+env:
+  CARGO_REGISTRY_TOKEN: ${{ secrets.CARGO_REGISTRY_TOKEN }}
+jobs:
+  release:
+    runs-on: ubuntu-latest
+    permissions:
+      contents: write
+      id-token: write
+    steps:
+      - uses: release-plz/action@v0.5
+        with:
+          command: release
+"#;
+        assert!(!finding(workflow, "token-exchange"));
+    }
+
+    fn migrated(workflow: &str) -> Migration {
+        Workflow::parse(workflow).migrate().expect("migrates")
+    }
+
+    fn text(migration: &Migration) -> String {
+        migration.workflow.lines().join("\n") + "\n"
+    }
+
+    #[test]
+    fn step_env_secret_is_migrated() {
+        let workflow = r#"on:
+  push:
+    tags: ["v*"]
+jobs:
+  publish:
+    runs-on: ubuntu-latest
+    steps:
+      - uses: actions/checkout@v5
+      - run: cargo publish
+        env:
+          CARGO_REGISTRY_TOKEN: ${{ secrets.CARGO_REGISTRY_TOKEN }}
+"#;
+        let migration = migrated(workflow);
+        assert_eq!(
+            text(&migration),
+            r#"on:
+  push:
+    tags: ["v*"]
+jobs:
+  publish:
+    runs-on: ubuntu-latest
+    permissions:
+      contents: read
+      id-token: write
+    steps:
+      - uses: actions/checkout@v5
+      - uses: rust-lang/crates-io-auth-action@v1
+        id: auth
+      - run: cargo publish
+        env:
+          CARGO_REGISTRY_TOKEN: ${{ steps.auth.outputs.token }}
+"#
+        );
+        assert!(migration.manual.is_empty());
+    }
+
+    #[test]
+    fn with_input_and_token_flag_are_migrated() {
+        let migration = migrated(
+            r#"on: push
+jobs:
+  publish:
+    permissions:
+      id-token: write
+    steps:
+      - uses: actions/checkout@v5
+      - uses: katyo/publish-crates@v2
+        with:
+          registry-token: ${{ secrets.CARGO_REGISTRY_TOKEN }}
+      - run: cargo publish --token ${{ secrets.CARGO_REGISTRY_TOKEN }}
+"#,
+        );
+        assert_eq!(
+            text(&migration),
+            r#"on: push
+jobs:
+  publish:
+    permissions:
+      id-token: write
+    steps:
+      - uses: actions/checkout@v5
+      - uses: rust-lang/crates-io-auth-action@v1
+        id: auth
+      - uses: katyo/publish-crates@v2
+        with:
+          registry-token: ${{ steps.auth.outputs.token }}
+      - run: cargo publish --token ${{ steps.auth.outputs.token }}
+"#
+        );
+    }
+
+    #[test]
+    fn job_block_permissions_gain_id_token() {
+        let workflow = r#"on:
+  push:
+    tags: ["v*"]
+jobs:
+  publish:
+    runs-on: ubuntu-latest
+    permissions:
+      contents: read
+    steps:
+      - uses: actions/checkout@v5
+      - run: cargo publish
+        env:
+          CARGO_REGISTRY_TOKEN: ${{ secrets.CARGO_REGISTRY_TOKEN }}
+"#;
+        let migration = migrated(workflow);
+        assert!(text(&migration).contains("    permissions:\n      contents: read\n      id-token: write\n    steps:\n"));
+    }
+
+    #[test]
+    fn job_flow_permissions_gain_id_token() {
+        let workflow = r#"on:
+  push:
+    tags: ["v*"]
+jobs:
+  publish:
+    runs-on: ubuntu-latest
+    permissions: { contents: read }
+    steps:
+      - uses: actions/checkout@v5
+      - run: cargo publish
+        env:
+          CARGO_REGISTRY_TOKEN: ${{ secrets.CARGO_REGISTRY_TOKEN }}
+"#;
+        let migration = migrated(workflow);
+        assert!(text(&migration).contains("    permissions: { contents: read, id-token: write }\n"));
+    }
+
+    #[test]
+    fn workflow_block_permissions_are_carried_into_the_job() {
+        let workflow = r#"on:
+  push:
+    tags: ["v*"]
+permissions:
+  contents: write
+jobs:
+  publish:
+    runs-on: ubuntu-latest
+    steps:
+      - uses: actions/checkout@v5
+      - run: cargo publish
+        env:
+          CARGO_REGISTRY_TOKEN: ${{ secrets.CARGO_REGISTRY_TOKEN }}
+"#;
+        let migration = migrated(workflow);
+        let text = text(&migration);
+        assert!(text.starts_with("on:\n  push:\n    tags: [\"v*\"]\npermissions:\n  contents: write\njobs:\n"));
+        assert!(text.contains("    permissions:\n      contents: write\n      id-token: write\n    steps:\n"));
+    }
+
+    #[test]
+    fn workflow_flow_permissions_are_carried_into_the_job() {
+        let workflow = r#"on:
+  push:
+    tags: ["v*"]
+permissions: { contents: write }
+jobs:
+  publish:
+    runs-on: ubuntu-latest
+    steps:
+      - uses: actions/checkout@v5
+      - run: cargo publish
+        env:
+          CARGO_REGISTRY_TOKEN: ${{ secrets.CARGO_REGISTRY_TOKEN }}
+"#;
+        let migration = migrated(workflow);
+        assert!(text(&migration).contains("    permissions: { contents: write, id-token: write }\n    steps:\n"));
+    }
+
+    #[test]
+    fn workflow_granting_id_token_leaves_the_job_alone() {
+        let workflow = r#"on:
+  push:
+    tags: ["v*"]
+permissions:
+  id-token: write
+jobs:
+  publish:
+    runs-on: ubuntu-latest
+    steps:
+      - uses: actions/checkout@v5
+      - run: cargo publish
+        env:
+          CARGO_REGISTRY_TOKEN: ${{ secrets.CARGO_REGISTRY_TOKEN }}
+"#;
+        let migration = migrated(workflow);
+        assert!(!text(&migration).contains("    permissions:"));
+    }
+
+    #[test]
+    fn read_all_is_left_to_do_by_hand() {
+        let workflow = r#"on:
+  push:
+    tags: ["v*"]
+permissions: read-all
+jobs:
+  publish:
+    runs-on: ubuntu-latest
+    steps:
+      - uses: actions/checkout@v5
+      - run: cargo publish
+        env:
+          CARGO_REGISTRY_TOKEN: ${{ secrets.CARGO_REGISTRY_TOKEN }}
+"#;
+        let err = Workflow::parse(workflow).migrate().err().expect("bails");
+        assert!(err.to_string().contains("`permissions: read-all`"));
+
+        let workflow = r#"on:
+  push:
+    tags: ["v*"]
+jobs:
+  publish:
+    runs-on: ubuntu-latest
+    permissions: read-all
+    steps:
+      - uses: actions/checkout@v5
+      - run: cargo publish
+        env:
+          CARGO_REGISTRY_TOKEN: ${{ secrets.CARGO_REGISTRY_TOKEN }}
+"#;
+        let err = Workflow::parse(workflow).migrate().err().expect("bails");
+        assert!(err.to_string().contains("`permissions: read-all`"));
+    }
+
+    #[test]
+    fn existing_auth_step_gets_an_id() {
+        let workflow = r#"on:
+  push:
+    tags: ["v*"]
+jobs:
+  publish:
+    runs-on: ubuntu-latest
+    permissions:
+      id-token: write
+    steps:
+      - uses: actions/checkout@v5
+      - uses: rust-lang/crates-io-auth-action@v1
+      - run: cargo publish
+        env:
+          CARGO_REGISTRY_TOKEN: ${{ secrets.CARGO_REGISTRY_TOKEN }}
+"#;
+        let migration = migrated(workflow);
+        let text = text(&migration);
+        assert!(text.contains("      - uses: rust-lang/crates-io-auth-action@v1\n        id: auth\n      - run: cargo publish\n"));
+        assert_eq!(text.matches("crates-io-auth-action").count(), 1);
+        assert!(text.contains("${{ steps.auth.outputs.token }}"));
+    }
+
+    #[test]
+    fn taken_step_id_is_not_reused() {
+        let workflow = r#"on:
+  push:
+    tags: ["v*"]
+jobs:
+  publish:
+    runs-on: ubuntu-latest
+    steps:
+      - uses: actions/checkout@v5
+        id: auth
+      - run: cargo publish
+        env:
+          CARGO_REGISTRY_TOKEN: ${{ secrets.CARGO_REGISTRY_TOKEN }}
+"#;
+        let text = text(&migrated(workflow));
+        assert!(text.contains("        id: crates-io-auth\n"));
+        assert!(text.contains("${{ steps.crates-io-auth.outputs.token }}"));
+    }
+
+    #[test]
+    fn steps_at_the_key_indentation_are_migrated() {
+        let workflow = r#"on: push
+jobs:
+  publish:
+    permissions:
+      id-token: write
+    steps:
+    - run: cargo publish
+      env:
+        CARGO_REGISTRY_TOKEN: ${{ secrets.CARGO_REGISTRY_TOKEN }}
+"#;
+        let migration = migrated(workflow);
+        assert!(text(&migration).contains(
+            "    steps:\n    - uses: rust-lang/crates-io-auth-action@v1\n      id: auth\n    - run: cargo publish\n"
+        ));
+        assert!(migration.manual.is_empty());
+    }
+
+    #[test]
+    fn job_env_secret_is_left_to_do_by_hand() {
+        let workflow = r#"on: push
+jobs:
+  publish:
+    env:
+      CARGO_REGISTRY_TOKEN: ${{ secrets.CARGO_REGISTRY_TOKEN }}
+    steps:
+      - run: cargo publish
+"#;
+        let migration = migrated(workflow);
+        assert!(migration.changes.is_empty());
+        assert_eq!(migration.manual.len(), 1);
+        assert!(migration.manual[0].contains("outside its steps"));
+    }
+
+    #[test]
+    fn workflow_env_secret_is_left_to_do_by_hand() {
+        let workflow = r#"on: push
+env:
+  CARGO_REGISTRY_TOKEN: ${{ secrets.CARGO_REGISTRY_TOKEN }}
+jobs:
+  publish:
+    steps:
+      - run: cargo publish
+"#;
+        let migration = migrated(workflow);
+        assert!(migration.changes.is_empty());
+        assert_eq!(migration.manual.len(), 1);
+        assert!(migration.manual[0].contains("outside its steps"));
+    }
+
+    #[test]
+    fn release_plz_without_id_token_is_told_to_grant_it() {
+        let workflow = r#"on:
+  push:
+    branches: [main]
+jobs:
+  release:
+    runs-on: ubuntu-latest
+    permissions:
+      contents: write
+    steps:
+      - uses: release-plz/action@v0.5
+        with:
+          command: release
+"#;
+        let migration = migrated(workflow);
+        assert_eq!(migration.manual.len(), 1);
+        assert!(migration.manual[0].contains("without `id-token: write`"));
+        assert!(migrated(RELEASE_PLZ).manual.is_empty());
+    }
+
+    #[test]
+    fn crlf_workflows_migrate_like_lf_ones() {
+        let lf = r#"on:
+  push:
+    tags: ["v*"]
+jobs:
+  publish:
+    runs-on: ubuntu-latest
+    steps:
+      - uses: actions/checkout@v5
+      - run: cargo publish
+        env:
+          CARGO_REGISTRY_TOKEN: ${{ secrets.CARGO_REGISTRY_TOKEN }}
+"#;
+        let crlf = lf.replace('\n', "\r\n");
+        assert_eq!(text(&migrated(&crlf)), text(&migrated(lf)));
+    }
+}